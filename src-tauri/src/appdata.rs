@@ -89,6 +89,10 @@ pub struct Settings {
 
 	pub my_workshop_local_paths: HashMap<PublishedFileId, PathBuf>,
 	pub upscale_addon_icon: bool,
+	/// Packs GMAs for publishing with `GMACreateOptions::deterministic`
+	pub deterministic_packing: bool,
+	/// Compresses GMAs before they're published to show how big their download will be, which takes a while for big addons
+	pub measure_download_size: bool,
 
 	pub language: Option<String>,
 
@@ -118,6 +122,8 @@ impl Default for Settings {
 			ignore_globs: Vec::new(),
 			my_workshop_local_paths: HashMap::new(),
 			upscale_addon_icon: true,
+			deterministic_packing: false,
+			measure_download_size: false,
 
 			language: None,
 
@@ -1,16 +1,41 @@
-use std::path::PathBuf;
+use std::{
+	io::{BufWriter, Read, Write},
+	path::{Path, PathBuf},
+};
+
+use clap::ArgMatches;
+use serde::Serialize;
+use steamworks::PublishedFileId;
 
 use crate::{
-	gma::{ExtractDestination, ExtractGMAMut},
-	GMAFile,
+	gma::{
+		whitelist, whitelist_violations, ArchiveFormat, ExtractDestination, ExtractFilter, ExtractGMAMut, ExtractReport, GMABuilder,
+		GMAChecksumStatus, GMACreateOptions, GMAEditSource, GMAEditor, GMAEntry, GMAHeader, GMAMergeConflict, GMAMergePolicy, GMAMetadata,
+		DEFAULT_LZMA_PRESET,
+	},
+	steam::{
+		headless::{publish_headless, HeadlessPublish, PublishProgress, WorkshopUploadStatus},
+		publishing::PublishError,
+	},
+	GMAError, GMAFile,
 };
 
+/// Paths given as this are read from stdin or written to stdout
+const STDIO: &str = "-";
+
+/// Exit code of `publish` when Steam isn't running or couldn't be connected to
+const EXIT_STEAM_UNAVAILABLE: i32 = 3;
+/// Exit code of `publish` when the upload worked, but the item stays hidden until the Workshop legal agreement is accepted
+const EXIT_LEGAL_AGREEMENT: i32 = 4;
+
+const STEAM_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
 lazy_static! {
 	pub static ref CLI_MODE: bool = std::env::args_os().len() > 1;
 }
 
 pub(super) fn stdin() -> bool {
-	use clap::{Arg, Command};
+	use clap::{Arg, ArgAction, Command};
 
 	if !*CLI_MODE {
 		return false;
@@ -25,13 +50,287 @@ pub(super) fn stdin() -> bool {
 	.version(env!("CARGO_PKG_VERSION"))
 	.author("William Venner <william@venner.io>")
 	.about("Publish, extract and work with GMA files")
+	.args_conflicts_with_subcommands(true)
+	.subcommands([
+		Command::new("info")
+		.about("Prints the metadata of a .GMA file")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file, or - to read it from stdin"),
+
+			Arg::new("format")
+			.long("format")
+			.value_parser(["text", "json"])
+			.default_value("text"),
+		]),
+
+		Command::new("list")
+		.about("Prints the CRC, size and path of every entry in a .GMA file")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file, or - to read it from stdin"),
+
+			Arg::new("format")
+			.long("format")
+			.value_parser(["text", "json"])
+			.default_value("text"),
+		]),
+
+		Command::new("extract")
+		.about("Extracts a .GMA file, to the temp directory unless told otherwise")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file, or - to read it from stdin"),
+
+			Arg::new("out")
+			.short('o')
+			.long("out")
+			.value_name("PATH")
+			.help("Extracts into PATH, or writes the entry there when used with --entry")
+			.conflicts_with_all(["addons", "downloads"]),
+
+			Arg::new("named")
+			.long("named")
+			.action(ArgAction::SetTrue)
+			.help("Extracts into a folder named after the addon inside the --out PATH")
+			.requires("out"),
+
+			Arg::new("addons")
+			.long("addons")
+			.action(ArgAction::SetTrue)
+			.help("Extracts into the GarrysMod/garrysmod/addons folder")
+			.conflicts_with("downloads"),
+
+			Arg::new("downloads")
+			.long("downloads")
+			.action(ArgAction::SetTrue)
+			.help("Extracts into the downloads folder"),
+
+			Arg::new("filter")
+			.long("filter")
+			.value_name("GLOB")
+			.action(ArgAction::Append)
+			.help("Only extracts entries matching GLOB, or not matching it if it starts with !. Can be given multiple times."),
+
+			Arg::new("whitelist")
+			.long("whitelist")
+			.action(ArgAction::SetTrue)
+			.help("Skips entries that aren't whitelisted"),
+
+			Arg::new("entry")
+			.long("entry")
+			.value_name("ENTRY")
+			.help("Only extracts ENTRY, to stdout unless --out is given")
+			.conflicts_with_all(["named", "addons", "downloads", "filter", "whitelist"]),
+		]),
+
+		Command::new("pack")
+		.about("Packs an addon folder into a .GMA file, using its addon.json")
+		.args([
+			Arg::new("src")
+			.value_name("SRC")
+			.required(true)
+			.help("The addon folder, which must contain an addon.json"),
+
+			Arg::new("out")
+			.value_name("OUT")
+			.required(true)
+			.help("The .GMA file to write, or - to write it to stdout"),
+
+			Arg::new("deterministic")
+			.long("deterministic")
+			.action(ArgAction::SetTrue)
+			.help("Uses a timestamp of 0 unless SOURCE_DATE_EPOCH is set, and sorts the tags and ignore globs, so the same folder always packs to the same bytes"),
+		]),
+
+		Command::new("check")
+		.about("Checks an addon folder or .GMA file against the whitelist, exiting with a non-zero status if anything isn't whitelisted")
+		.arg(
+			Arg::new("path")
+			.value_name("PATH")
+			.required(true)
+			.help("The addon folder or .GMA file, or - to read a .GMA file from stdin"),
+		),
+
+		Command::new("verify")
+		.about("Verifies the checksums of a .GMA file and its entries, exiting with a non-zero status if any entry is corrupted")
+		.arg(
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file, or - to read it from stdin"),
+		),
+
+		Command::new("diff")
+		.about("Prints the added, removed and modified entries between a .GMA file and another .GMA file or source directory, as JSON")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The old .GMA file, or - to read it from stdin"),
+
+			Arg::new("other")
+			.value_name("PATH")
+			.required(true)
+			.help("The new .GMA file, or an addon folder as it would be packed"),
+		]),
+
+		Command::new("edit")
+		.about("Edits the entries of a .GMA file in place")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file"),
+
+			Arg::new("add")
+			.long("add")
+			.value_name("ENTRY=PATH")
+			.action(ArgAction::Append)
+			.help("Adds the file at PATH as ENTRY. Can be given multiple times."),
+
+			Arg::new("replace")
+			.long("replace")
+			.value_name("ENTRY=PATH")
+			.action(ArgAction::Append)
+			.help("Replaces ENTRY with the file at PATH. Can be given multiple times."),
+
+			Arg::new("remove")
+			.long("remove")
+			.value_name("ENTRY")
+			.action(ArgAction::Append)
+			.help("Removes ENTRY. Can be given multiple times."),
+		]),
+
+		Command::new("compress")
+		.about("Compresses a .GMA file to the LZMA format of workshop .bin files")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file, or - to read it from stdin"),
+
+			Arg::new("out")
+			.value_name("OUT")
+			.help("The .bin file to write. Defaults to FILE with a .bin extension."),
+		]),
+
+		Command::new("to-archive")
+		.about("Exports the entries and addon.json of a .GMA file to a .zip or .tar archive, depending on the extension of OUT")
+		.args([
+			Arg::new("file")
+			.value_name("FILE")
+			.required(true)
+			.help("The .GMA file, or - to read it from stdin"),
+
+			Arg::new("out")
+			.value_name("OUT")
+			.required(true)
+			.help("The .zip or .tar file to write"),
+		]),
+
+		Command::new("from-zip")
+		.about("Packs the addon in a .zip archive, which must contain an addon.json, into a .GMA file")
+		.args([
+			Arg::new("zip")
+			.value_name("ZIP")
+			.required(true)
+			.help("The .zip file"),
+
+			Arg::new("out")
+			.value_name("OUT")
+			.required(true)
+			.help("The .GMA file to write"),
+		]),
+
+		Command::new("split")
+		.about("Splits a .GMA file, or an addon folder with an addon.json, into GMAs of at most SIZE bytes, named OUT_1.gma, OUT_2.gma...")
+		.args([
+			Arg::new("src")
+			.value_name("SRC")
+			.required(true)
+			.help("The .GMA file or addon folder"),
+
+			Arg::new("size")
+			.value_name("SIZE")
+			.required(true)
+			.help("The most bytes each GMA can be. K, M and G suffixes are allowed."),
+
+			Arg::new("out")
+			.value_name("OUT")
+			.required(true)
+			.help("The path the GMAs are named after"),
+		]),
+
+		Command::new("merge")
+		.about("Merges two or more .GMA files into one, which takes its metadata from the first one")
+		.args([
+			Arg::new("out")
+			.value_name("OUT")
+			.required(true)
+			.help("The .GMA file to write"),
+
+			Arg::new("files")
+			.value_name("FILE")
+			.required(true)
+			.num_args(2..)
+			.help("The .GMA files to merge, in order"),
+
+			Arg::new("conflicts")
+			.long("conflicts")
+			.value_name("POLICY")
+			.value_parser(["first", "last", "fail"])
+			.default_value("fail")
+			.help("What to do when the GMAs have different files at the same path"),
+		]),
+
+		Command::new("publish")
+		.about("Packs an addon folder and uploads it to the Steam Workshop, as a new item unless --update is given. Steam must be running.")
+		.after_help("Exit codes: 0 if it was published, 1 if it failed, 3 if Steam couldn't be connected to, and 4 if it was published but stays hidden until the Workshop legal agreement is accepted.")
+		.args([
+			Arg::new("path")
+			.value_name("PATH")
+			.required(true)
+			.help("The addon folder, whose addon.json gives the title, type, tags and ignore globs"),
+
+			Arg::new("update")
+			.short('u')
+			.long("update")
+			.value_name("PublishedFileId")
+			.value_parser(clap::value_parser!(u64))
+			.help("Publishes an update to this Workshop item instead of creating a new one"),
+
+			Arg::new("changes")
+			.long("changes")
+			.value_name("CHANGES")
+			.help("Sets the changelog for an update")
+			.requires("update"),
+
+			Arg::new("icon")
+			.long("icon")
+			.value_name("PATH")
+			.help("Path to a (max 1 MB) JPG/PNG/GIF file for the Workshop preview image. New items get the gmpublisher icon if this isn't given."),
+
+			Arg::new("deterministic")
+			.long("deterministic")
+			.action(ArgAction::SetTrue)
+			.help("Packs the same folder into the same bytes every time, see pack --deterministic"),
+		]),
+	])
+	// The original flags, kept as an alias of the extract subcommand
 	.args(&[
 		Arg::new("extract")
 		.short('e')
 		.long("extract")
 		.value_name("FILE")
-		.help("Extracts a .GMA file"),
-		//.conflicts_with_all(&["update", "in", "changes", "icon"]),
+		.help("Extracts a .GMA file, or one read from stdin if FILE is -")
+		.hide(true),
 
 		Arg::new("out")
 		.short('o')
@@ -39,65 +338,680 @@ pub(super) fn stdin() -> bool {
 		.value_name("PATH")
 		.help("Sets the output path for extracting GMAs. Defaults to the temp directory.")
 		.requires("extract")
-		//.conflicts_with_all(&["update", "in", "changes", "icon"])
+		.hide(true),
 	])
-	/*.args(&[
-		Arg::with_name("update")
-		.short('u')
-		.long("update")
-		.value_name("PublishedFileId")
-		.takes_value(true)
-		.help("Publishes an update.")
-		.requires("in")
-		.conflicts_with_all(&["out", "extract"]),
-
-		Arg::with_name("in")
-		.long("in")
-		.value_name("PATH")
-		.takes_value(true)
-		.help("Sets the directory the GMA for updating will be built from.")
-		.requires("update")
-		.conflicts_with_all(&["out", "extract"]),
-
-		Arg::with_name("changes")
-		.long("changes")
-		.value_name("CHANGES")
-		.takes_value(true)
-		.help("Sets the changelog for an update.")
-		.requires("update")
-		.conflicts_with_all(&["out", "extract"]),
-
-		Arg::with_name("icon")
-		.long("icon")
-		.value_name("PATH")
-		.takes_value(true)
-		.help("Path to a (max 1 MB) JPG/PNG/GIF file for Workshop preview image updating.")
-		.requires("update")
-		.conflicts_with_all(&["out", "extract"])
-	])*/
 	.get_matches();
 
-	dprintln!("{:#?}", matches);
+	// stdout might be piped somewhere
+	#[cfg(debug_assertions)]
+	std::eprintln!("{:#?}", matches);
+
+	if let Some((name, matches)) = matches.subcommand() {
+		subcommand(name, matches);
+		return true;
+	}
 
 	if let Some(extract_path) = matches.get_one::<String>("extract") {
-		let extract_path = PathBuf::from(extract_path);
+		let dest = match matches.get_one::<String>("out") {
+			Some(out) => ExtractDestination::Directory(PathBuf::from(out)),
+			None => ExtractDestination::Temp,
+		};
+
+		extract(Path::new(extract_path), dest, &ExtractFilter::default(), true, true);
+	}
+
+	true
+}
 
-		if !extract_path.is_file() {
-			std::eprintln!("Invalid GMA file path provided.");
-			return true;
+/// Every value given for `id`, which can be given multiple times
+fn values(matches: &ArgMatches, id: &str) -> Vec<String> {
+	matches.get_many::<String>(id).map(|values| values.cloned().collect()).unwrap_or_default()
+}
+
+fn merge_policy(matches: &ArgMatches) -> GMAMergePolicy {
+	match matches.get_one::<String>("conflicts").map(String::as_str) {
+		Some("first") => GMAMergePolicy::FirstWins,
+		Some("last") => GMAMergePolicy::LastWins,
+		_ => GMAMergePolicy::Fail,
+	}
+}
+
+/// Opens the GMA at `path`, or reads it from stdin if `path` is -
+fn open_gma(path: &Path) -> Result<GMAFile, GMAError> {
+	if path.as_os_str() == STDIO {
+		let mut bytes = Vec::new();
+		std::io::stdin().lock().read_to_end(&mut bytes)?;
+		return GMAFile::open_bytes(bytes, "stdin.gma");
+	}
+
+	if !path.is_file() {
+		std::eprintln!("Invalid GMA file path provided.");
+		std::process::exit(1);
+	}
+
+	GMAFile::open(path)
+}
+
+fn verify(path: PathBuf) {
+	let report = match open_gma(&path).and_then(|mut gma| gma.verify(&transaction!())) {
+		Ok(report) => report,
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	};
+
+	for entry in report.failed() {
+		match entry.computed_crc {
+			Some(computed_crc) => std::println!(
+				"{:?}: {} (expected {:08x}, got {:08x})",
+				entry.status,
+				entry.path,
+				entry.crc,
+				computed_crc
+			),
+			None => std::println!("{:?}: {}", entry.status, entry.path),
+		}
+	}
+
+	match report.checksum {
+		GMAChecksumStatus::Ok => std::println!("Checksum OK"),
+		GMAChecksumStatus::Mismatch { expected, computed } if report.ok => std::println!(
+			"Checksum mismatch (expected {:08x}, got {:08x}), but every entry is intact. Older versions of gmpublisher wrote this checksum incorrectly.",
+			expected, computed
+		),
+		GMAChecksumStatus::Mismatch { expected, computed } => std::println!("Checksum mismatch (expected {:08x}, got {:08x})", expected, computed),
+		GMAChecksumStatus::Missing => std::println!("Checksum missing"),
+		GMAChecksumStatus::TrailingData { bytes } => std::println!("Unexpected {} bytes of trailing data", bytes),
+	}
+
+	std::println!("{}/{} entries OK", report.entries.len() - report.failed().count(), report.entries.len());
+
+	if !report.ok {
+		std::process::exit(1);
+	}
+}
+
+fn diff(gma_path: PathBuf, other_path: PathBuf) {
+	if !other_path.exists() {
+		std::eprintln!("Invalid GMA file path provided.");
+		std::process::exit(1);
+	}
+
+	let result = open_gma(&gma_path).and_then(|mut gma| {
+		if other_path.is_dir() {
+			gma.diff_dir(other_path, &transaction!())
+		} else {
+			GMAFile::open(other_path).and_then(|mut other| gma.diff(&mut other))
+		}
+	});
+
+	match result {
+		Ok(diff) => std::println!("{}", serde_json::to_string_pretty(&diff).unwrap()),
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+fn edit(path: PathBuf, add: Vec<String>, replace: Vec<String>, remove: Vec<String>) {
+	if !path.is_file() {
+		std::eprintln!("Invalid GMA file path provided.");
+		std::process::exit(1);
+	}
+
+	let split = |args: Vec<String>| -> Vec<(String, PathBuf)> {
+		args.into_iter()
+			.map(|arg| match arg.split_once('=') {
+				Some((entry_path, path)) => (entry_path.to_owned(), PathBuf::from(path)),
+				None => {
+					std::eprintln!("Expected ENTRY=PATH, got \"{}\"", arg);
+					std::process::exit(1);
+				}
+			})
+			.collect()
+	};
+	let add = split(add);
+	let replace = split(replace);
+
+	let result = GMAFile::open(path).and_then(|mut gma| {
+		gma.entries()?;
+
+		let mut editor = GMAEditor::new(&gma);
+		for (entry_path, path) in add {
+			editor.add(entry_path, GMAEditSource::File(path))?;
+		}
+		for (entry_path, path) in replace {
+			editor.replace(entry_path, GMAEditSource::File(path))?;
+		}
+		for entry_path in remove {
+			editor.remove(entry_path)?;
+		}
+
+		editor.save(&transaction!())
+	});
+
+	if let Err(err) = result {
+		std::eprintln!("Error: {:#?}", err);
+		std::process::exit(1);
+	}
+}
+
+/// `out_path` defaults to `gma_path` with a .bin extension
+fn compress(gma_path: PathBuf, out_path: Option<PathBuf>) {
+	let out_path = match out_path {
+		Some(out_path) => out_path,
+		None if gma_path.as_os_str() == STDIO => {
+			std::eprintln!("OUT is required when compressing a GMA from stdin");
+			std::process::exit(1);
+		}
+		None => gma_path.with_extension("bin"),
+	};
+
+	match open_gma(&gma_path).and_then(|gma| gma.compress_to(&out_path, DEFAULT_LZMA_PRESET, &transaction!())) {
+		Ok(compressed_size) => std::println!("{} -> {} ({} bytes)", gma_path.display(), out_path.display(), compressed_size),
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+fn to_archive(gma_path: PathBuf, out_path: PathBuf) {
+	let format = match ArchiveFormat::from_path(&out_path) {
+		Some(format) => format,
+		None => {
+			std::eprintln!("Expected OUT to end in .zip or .tar");
+			std::process::exit(1);
 		}
+	};
+
+	if let Err(err) = open_gma(&gma_path).and_then(|mut gma| gma.export_archive(&out_path, format, &transaction!())) {
+		std::eprintln!("Error: {:#?}", err);
+		std::process::exit(1);
+	}
+
+	std::println!("{} -> {}", gma_path.display(), out_path.display());
+}
+
+fn from_zip(zip_path: PathBuf, out_path: PathBuf) {
+	if !zip_path.is_file() {
+		std::eprintln!("Invalid zip file path provided.");
+		std::process::exit(1);
+	}
+
+	let gma = GMAFile::new(out_path.clone(), None);
+
+	if let Err(err) = gma.create_from_zip(&zip_path, transaction!(), Default::default()) {
+		std::eprintln!("Error: {:#?}", err);
+		std::process::exit(1);
+	}
+
+	std::println!("{} -> {}", zip_path.display(), out_path.display());
+}
 
-		if let Ok(mut gma) = GMAFile::open(extract_path) {
-			let dest = match matches.get_one::<String>("out") {
-				Some(out) => ExtractDestination::Directory(PathBuf::from(out)),
+fn parse_size(size: &str) -> Option<u64> {
+	let size = size.trim();
+	let (number, multiplier) = match size.char_indices().last()? {
+		(i, 'k' | 'K') => (&size[..i], 1024),
+		(i, 'm' | 'M') => (&size[..i], 1024 * 1024),
+		(i, 'g' | 'G') => (&size[..i], 1024 * 1024 * 1024),
+		_ => (size, 1),
+	};
+	number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn split(src_path: PathBuf, size: &str, out_path: PathBuf) {
+	let budget = match parse_size(size) {
+		Some(budget) if budget > 0 => budget,
+		_ => {
+			std::eprintln!("Invalid size \"{}\"", size);
+			std::process::exit(1);
+		}
+	};
+
+	let result = if src_path.is_dir() {
+		let metadata = match GMAMetadata::read_addon_json(&src_path) {
+			Some(metadata) => metadata,
+			None => {
+				std::eprintln!("The addon folder doesn't contain a valid addon.json");
+				std::process::exit(1);
+			}
+		};
+
+		let gma = GMAFile::new(out_path, Some(metadata));
+		gma.create_split(&src_path, budget, &transaction!())
+	} else if src_path.is_file() {
+		GMAFile::open(&src_path).and_then(|mut gma| gma.split(&out_path, budget, &transaction!()))
+	} else {
+		std::eprintln!("Invalid GMA file or addon folder path provided.");
+		std::process::exit(1);
+	};
+
+	match result {
+		Ok(parts) => {
+			for part in parts {
+				std::println!(
+					"{} ({} entries, {} bytes) -> {}",
+					part.title,
+					part.entries.len(),
+					part.size,
+					part.path.display()
+				);
+			}
+		}
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+fn print_merge_conflicts(conflicts: &[GMAMergeConflict]) {
+	for conflict in conflicts {
+		std::println!("Conflict: {}", conflict.path);
+		for (i, source) in conflict.sources.iter().enumerate() {
+			let kept = if conflict.kept == Some(i) { " (kept)" } else { "" };
+			std::println!("\t{} ({} bytes, crc {:08x}){}", source.gma.display(), source.size, source.crc, kept);
+		}
+	}
+}
+
+fn merge(out_path: PathBuf, gma_paths: Vec<PathBuf>, policy: GMAMergePolicy) {
+	if let Some(gma_path) = gma_paths.iter().find(|gma_path| !gma_path.is_file()) {
+		std::eprintln!("Invalid GMA file path provided: {}", gma_path.display());
+		std::process::exit(1);
+	}
+
+	let result = gma_paths
+		.into_iter()
+		.map(GMAFile::open)
+		.collect::<Result<Vec<_>, _>>()
+		.and_then(|mut gmas| GMAFile::merge(&mut gmas, &out_path, policy, &transaction!()));
+
+	match result {
+		Ok(report) => {
+			print_merge_conflicts(&report.conflicts);
+			std::println!("Merged {} entries into {}", report.entries, report.path.display());
+		}
+		Err(GMAError::MergeConflict(conflicts)) => {
+			print_merge_conflicts(&conflicts);
+			std::eprintln!(
+				"{} conflicting paths, nothing was merged. Use --conflicts first or --conflicts last to resolve them.",
+				conflicts.len()
+			);
+			std::process::exit(1);
+		}
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+fn subcommand(name: &str, matches: &ArgMatches) {
+	let path = |id: &str| PathBuf::from(matches.get_one::<String>(id).unwrap());
+	let json = || matches.get_one::<String>("format").map(String::as_str) == Some("json");
+
+	match name {
+		"info" => info(path("file"), json()),
+		"list" => list(path("file"), json()),
+		"extract" => {
+			let gma_path = path("file");
+			let out_path = matches.get_one::<String>("out").map(PathBuf::from);
+
+			if let Some(entry_path) = matches.get_one::<String>("entry") {
+				extract_entry(gma_path, entry_path, out_path.unwrap_or_else(|| PathBuf::from(STDIO)));
+				return;
+			}
+
+			let dest = match out_path {
+				Some(out_path) if matches.get_flag("named") => ExtractDestination::NamedDirectory(out_path),
+				Some(out_path) => ExtractDestination::Directory(out_path),
+				None if matches.get_flag("addons") => ExtractDestination::Addons,
+				None if matches.get_flag("downloads") => ExtractDestination::Downloads,
 				None => ExtractDestination::Temp,
 			};
 
-			if let Err(err) = gma.extract(dest, &transaction!(), true, true) {
-				std::eprintln!("Error: {:#?}", err);
+			let filter = ExtractFilter::new(matches.get_many::<String>("filter").into_iter().flatten());
+
+			extract(&gma_path, dest, &filter, false, !matches.get_flag("whitelist"));
+		}
+		"pack" => {
+			let options = if matches.get_flag("deterministic") {
+				GMACreateOptions::deterministic()
+			} else {
+				GMACreateOptions::default()
+			};
+			pack(path("src"), path("out"), options);
+		}
+		"check" => check(path("path")),
+		"verify" => verify(path("file")),
+		"diff" => diff(path("file"), path("other")),
+		"edit" => edit(
+			path("file"),
+			values(matches, "add"),
+			values(matches, "replace"),
+			values(matches, "remove"),
+		),
+		"compress" => compress(path("file"), matches.get_one::<String>("out").map(PathBuf::from)),
+		"to-archive" => to_archive(path("file"), path("out")),
+		"from-zip" => from_zip(path("zip"), path("out")),
+		"split" => split(path("src"), matches.get_one::<String>("size").unwrap(), path("out")),
+		"merge" => merge(
+			path("out"),
+			values(matches, "files").into_iter().map(PathBuf::from).collect(),
+			merge_policy(matches),
+		),
+		"publish" => publish(HeadlessPublish {
+			content_path: path("path"),
+			update_id: matches.get_one::<u64>("update").map(|id| PublishedFileId(*id)),
+			icon_path: matches.get_one::<String>("icon").map(PathBuf::from),
+			changes: matches.get_one::<String>("changes").cloned(),
+			deterministic: matches.get_flag("deterministic"),
+			staging_dir: std::env::temp_dir().join(format!("gmpublisher_publishing_{}", std::process::id())),
+		}),
+		_ => unreachable!(),
+	}
+}
+
+fn extract(gma_path: &Path, dest: ExtractDestination, filter: &ExtractFilter, open_after_extract: bool, ignore_whitelist: bool) {
+	let result = open_gma(gma_path).and_then(|mut gma| gma.extract_filtered(dest, filter, &transaction!(), open_after_extract, ignore_whitelist));
+
+	match result {
+		Ok(report) => {
+			print_extract_report(&report);
+			if !report.failed.is_empty() {
+				std::process::exit(1);
 			}
 		}
+		Err(GMAError::ExtractionFailed(report)) => {
+			print_extract_report(&report);
+			std::eprintln!("Error: none of the entries could be extracted");
+			std::process::exit(1);
+		}
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
 	}
+}
 
-	true
+fn print_extract_report(report: &ExtractReport) {
+	for path in report.skipped.iter() {
+		std::println!("Skipped (not whitelisted): {}", path);
+	}
+	for failure in report.failed.iter() {
+		std::println!("Failed ({}): {}", failure.error, failure.path);
+	}
+	for rejected in report.rejected.iter() {
+		std::println!("Rejected ({:?}): {}", rejected.reason, rejected.path);
+	}
+	std::println!("Extracted {} entries to {}", report.extracted.len(), report.path.display());
+	if report.unchanged > 0 || report.deleted > 0 {
+		std::println!("{} entries unchanged, {} files deleted", report.unchanged, report.deleted);
+	}
+}
+
+/// Opens the GMA at `gma_path` and reads its metadata and entries, exiting if that fails
+fn read_gma(gma_path: &Path) -> GMAFile {
+	let result = open_gma(gma_path).and_then(|mut gma| {
+		gma.entries()?;
+		Ok(gma)
+	});
+
+	match result {
+		Ok(gma) => gma,
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct GMAInfo<'a> {
+	path: &'a Path,
+	size: u64,
+	version: u8,
+	metadata: &'a GMAMetadata,
+	header: &'a GMAHeader,
+	entries: usize,
+	entries_size: u64,
+	duplicates: usize,
+}
+
+fn info(gma_path: PathBuf, json: bool) {
+	let gma = read_gma(&gma_path);
+	let metadata = gma.metadata.as_ref().unwrap();
+	let header = gma.header.as_ref().unwrap();
+	let entries = gma.entries.as_ref().unwrap();
+
+	let info = GMAInfo {
+		path: &gma.path,
+		size: gma.size,
+		version: gma.version,
+		metadata,
+		header,
+		entries: entries.len(),
+		entries_size: entries.iter().map(|entry| entry.size).sum(),
+		duplicates: entries.duplicates().count(),
+	};
+
+	if json {
+		std::println!("{}", serde_json::to_string_pretty(&info).unwrap());
+		return;
+	}
+
+	std::println!("Title: {}", metadata.title());
+	match metadata {
+		GMAMetadata::Standard {
+			addon_type, tags, ignore, ..
+		} => {
+			std::println!("Type: {}", addon_type);
+			std::println!("Tags: {}", tags.join(", "));
+			if !ignore.is_empty() {
+				std::println!("Ignore: {}", ignore.join(", "));
+			}
+		}
+		GMAMetadata::Legacy { description, .. } => std::println!("Description: {}", description),
+	}
+	std::println!("Author: {}", header.author);
+	std::println!("SteamID64: {}", header.steamid);
+	std::println!("Timestamp: {}", header.timestamp);
+	std::println!("Addon version: {}", header.addon_version);
+	if !header.required_content.is_empty() {
+		std::println!("Required content: {}", header.required_content.join(", "));
+	}
+	std::println!("GMA version: {}", info.version);
+	std::println!("Entries: {} ({} bytes)", info.entries, info.entries_size);
+	if info.duplicates > 0 {
+		std::println!("Duplicates: {} entries share their path with another", info.duplicates);
+	}
+	std::println!("Size: {} bytes", info.size);
+}
+
+fn list(gma_path: PathBuf, json: bool) {
+	let gma = read_gma(&gma_path);
+	let entries = gma.entries.as_ref().unwrap();
+
+	if json {
+		std::println!("{}", serde_json::to_string_pretty(&entries.iter().collect::<Vec<&GMAEntry>>()).unwrap());
+		return;
+	}
+
+	for entry in entries.iter() {
+		if entry.duplicate {
+			std::println!("{:08x}\t{}\t{}\t(duplicate)", entry.crc, entry.size, entry.path);
+		} else {
+			std::println!("{:08x}\t{}\t{}", entry.crc, entry.size, entry.path);
+		}
+	}
+}
+
+fn extract_entry(gma_path: PathBuf, entry_path: &str, out_path: PathBuf) {
+	let result = open_gma(&gma_path).and_then(|mut gma| {
+		gma.entries()?;
+
+		let mut r = gma.open_entry(entry_path)?;
+		if out_path.as_os_str() == STDIO {
+			let mut stdout = std::io::stdout().lock();
+			std::io::copy(&mut r, &mut stdout)?;
+			stdout.flush()?;
+		} else {
+			std::io::copy(&mut r, &mut std::fs::File::create(&out_path)?)?;
+		}
+		Ok(())
+	});
+
+	if let Err(err) = result {
+		std::eprintln!("Error: {:#?}", err);
+		std::process::exit(1);
+	}
+}
+
+fn pack(src_path: PathBuf, out_path: PathBuf, options: GMACreateOptions) {
+	if !src_path.is_dir() {
+		std::eprintln!("Invalid addon folder path provided.");
+		std::process::exit(1);
+	}
+
+	let metadata = match GMAMetadata::read_addon_json(&src_path) {
+		Some(metadata) => metadata,
+		None => {
+			std::eprintln!("The addon folder doesn't contain a valid addon.json");
+			std::process::exit(1);
+		}
+	};
+
+	let result = if out_path.as_os_str() == STDIO {
+		pack_to_stdout(&src_path, metadata, options)
+	} else {
+		let gma = GMAFile::new(out_path.clone(), Some(metadata));
+		gma.create_with_options(&src_path, transaction!(), options).map(|_| {
+			std::println!("{} -> {}", src_path.display(), out_path.display());
+		})
+	};
+
+	if let Err(err) = result {
+		std::eprintln!("Error: {:#?}", err);
+		std::process::exit(1);
+	}
+}
+
+/// Pipes can't seek, so the CRCs are computed before the GMA is streamed out
+fn pack_to_stdout(src_path: &Path, metadata: GMAMetadata, options: GMACreateOptions) -> Result<(), GMAError> {
+	let transaction = transaction!();
+	let mut builder = GMABuilder::new(metadata);
+	builder.options(options).add_dir(src_path, &transaction)?;
+	builder.write_to_stream(BufWriter::new(std::io::stdout().lock()), &transaction)?;
+	Ok(())
+}
+
+fn check(path: PathBuf) {
+	let violations = if path.is_dir() {
+		let metadata = GMAMetadata::read_addon_json(&path);
+		whitelist_violations(
+			&path,
+			metadata.as_ref().and_then(|metadata| metadata.ignore()).map(|ignore| ignore.as_slice()),
+		)
+	} else {
+		// Shadowed duplicates are still in the GMA, so they're checked too
+		let gma = read_gma(&path);
+		let mut violations = gma
+			.entries
+			.as_ref()
+			.unwrap()
+			.iter()
+			.filter(|entry| !whitelist::check(&entry.path))
+			.map(|entry| entry.path.clone())
+			.collect::<Vec<String>>();
+		violations.sort();
+		violations.dedup();
+		violations
+	};
+
+	for path in violations.iter() {
+		std::println!("Not whitelisted: {}", path);
+	}
+
+	if violations.is_empty() {
+		std::println!("Everything is whitelisted");
+	} else {
+		std::eprintln!("{} files aren't whitelisted", violations.len());
+		std::process::exit(1);
+	}
+}
+
+fn upload_status_text(status: WorkshopUploadStatus) -> &'static str {
+	match status {
+		WorkshopUploadStatus::PreparingConfig => "Preparing config",
+		WorkshopUploadStatus::PreparingContent => "Preparing content",
+		WorkshopUploadStatus::UploadingContent => "Uploading content",
+		WorkshopUploadStatus::UploadingPreviewFile => "Uploading preview image",
+		WorkshopUploadStatus::CommittingChanges => "Committing changes",
+	}
+}
+
+fn publish(request: HeadlessPublish) {
+	std::eprintln!("Connecting to Steam...");
+	let started = std::time::Instant::now();
+	while !steam!().connected() {
+		if started.elapsed() >= STEAM_CONNECT_TIMEOUT {
+			std::eprintln!("Couldn't connect to Steam. Make sure Steam is running and you're logged in.");
+			std::process::exit(EXIT_STEAM_UNAVAILABLE);
+		}
+		sleep_ms!(50);
+	}
+
+	// Progress goes to stderr, so stdout only gets the ID of the item
+	let mut last_stage = "";
+	let mut last_percent = None;
+	let result = publish_headless(&**steam!(), request, &mut |progress| {
+		let (stage, percent) = match progress {
+			PublishProgress::Packing => ("Packing", None),
+			PublishProgress::CreatingItem => ("Creating Workshop item", None),
+			PublishProgress::Uploading { status, done, total } => (upload_status_text(status), (total > 0).then(|| done * 100 / total)),
+		};
+
+		if stage != last_stage || percent != last_percent {
+			match percent {
+				Some(percent) => std::eprintln!("{}... {}%", stage, percent),
+				None if stage != last_stage => std::eprintln!("{}...", stage),
+				None => {}
+			}
+			last_stage = stage;
+			last_percent = percent;
+		}
+	});
+
+	match result {
+		Ok(outcome) => {
+			std::println!("{}", outcome.id.0);
+			std::eprintln!(
+				"{} https://steamcommunity.com/sharedfiles/filedetails/?id={}",
+				if outcome.created { "Published" } else { "Updated" },
+				outcome.id.0
+			);
+
+			if outcome.legal_agreement_required {
+				std::eprintln!(
+					"The item stays hidden until you accept the Steam Workshop legal agreement: https://steamcommunity.com/workshop/workshoplegalagreement"
+				);
+				std::process::exit(EXIT_LEGAL_AGREEMENT);
+			}
+		}
+		Err(PublishError::NotWhitelisted(paths)) => {
+			for path in paths {
+				std::eprintln!("Not whitelisted: {}", path);
+			}
+			std::eprintln!("Nothing was published");
+			std::process::exit(1);
+		}
+		Err(err) => {
+			std::eprintln!("Error: {:#?}", err);
+			std::process::exit(1);
+		}
+	}
 }
@@ -39,7 +39,12 @@ pub fn invoke_handler() -> impl Fn(tauri::Invoke<tauri::Wry>) + Send + Sync + 's
 		crate::gma::preview::preview_gma,
 		crate::gma::preview::extract_preview_entry,
 		crate::gma::preview::extract_preview_gma,
+		crate::gma::preview::edit_preview_gma,
 		crate::gma::extract::extract_gma,
+		crate::gma::verify::verify_gma,
+		crate::gma::diff::diff_gma,
+		crate::gma::compress::compress_gma,
+		crate::gma::merge::merge_gmas,
 		crate::search::search,
 		crate::search::search_channel,
 		crate::search::full_search,
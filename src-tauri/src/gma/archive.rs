@@ -0,0 +1,296 @@
+use std::{
+	collections::BTreeMap,
+	fs::{self, File},
+	io::{BufReader, BufWriter, Read, SeekFrom, Write},
+	path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::transactions::Transaction;
+
+use super::{
+	sanitize,
+	write::{ignore_globs, source_path_allowed},
+	GMABuilder, GMACreateOptions, GMAError, GMAFile, GMAMetadata, GMAReader, GMAWriter,
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ArchiveFormat {
+	Zip,
+	Tar,
+}
+impl ArchiveFormat {
+	/// Guesses the format from the extension of `path`
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ArchiveFormat> {
+		let extension = path.as_ref().extension()?.to_str()?;
+		if extension.eq_ignore_ascii_case("zip") {
+			Some(ArchiveFormat::Zip)
+		} else if extension.eq_ignore_ascii_case("tar") {
+			Some(ArchiveFormat::Tar)
+		} else {
+			None
+		}
+	}
+}
+
+impl From<zip::result::ZipError> for GMAError {
+	fn from(error: zip::result::ZipError) -> Self {
+		match error {
+			zip::result::ZipError::Io(error) => error.into(),
+			_ => GMAError::InvalidArchive,
+		}
+	}
+}
+
+enum ArchiveWriter {
+	Zip(zip::ZipWriter<BufWriter<File>>),
+	Tar(tar::Builder<BufWriter<File>>),
+}
+impl ArchiveWriter {
+	fn append<R: Read>(&mut self, path: &str, size: u64, timestamp: u64, r: R) -> Result<(), GMAError> {
+		match self {
+			ArchiveWriter::Zip(w) => {
+				let options = zip::write::FileOptions::default()
+					.compression_method(zip::CompressionMethod::Deflated)
+					.large_file(size >= u32::MAX as u64);
+				w.start_file(path, options)?;
+				std::io::copy(&mut r.take(size), w)?;
+			}
+			ArchiveWriter::Tar(w) => {
+				let mut header = tar::Header::new_gnu();
+				header.set_entry_type(tar::EntryType::Regular);
+				header.set_size(size);
+				header.set_mode(0o644);
+				header.set_mtime(timestamp);
+				w.append_data(&mut header, path, r.take(size))?;
+			}
+		}
+		Ok(())
+	}
+
+	fn finish(self) -> Result<(), GMAError> {
+		match self {
+			ArchiveWriter::Zip(mut w) => w.finish()?.flush()?,
+			ArchiveWriter::Tar(w) => w.into_inner()?.flush()?,
+		}
+		Ok(())
+	}
+}
+
+impl GMAFile {
+	/// Exports the entries of this GMA to a zip or tar archive, along with its addon.json
+	pub fn export_archive<P: AsRef<Path>>(&mut self, dest: P, format: ArchiveFormat, transaction: &Transaction) -> Result<(), GMAError> {
+		main_thread_forbidden!();
+
+		let mut handle = match self.entries()? {
+			Some(handle) => handle,
+			None => self.read()?,
+		};
+
+		let dest = dest.as_ref();
+		let f = BufWriter::new(File::create(dest)?);
+		let w = match format {
+			ArchiveFormat::Zip => ArchiveWriter::Zip(zip::ZipWriter::new(f)),
+			ArchiveFormat::Tar => ArchiveWriter::Tar(tar::Builder::new(f)),
+		};
+
+		let result = self.write_archive(w, &mut handle, transaction);
+		if result.is_err() {
+			fs::remove_file(dest).ok();
+		}
+		result
+	}
+
+	fn write_archive(&self, mut w: ArchiveWriter, handle: &mut GMAReader, transaction: &Transaction) -> Result<(), GMAError> {
+		let entries = self.entries.as_ref().expect("Expected entries to be read by this point");
+		let timestamp = self.header.as_ref().map(|header| header.timestamp).unwrap_or(0);
+
+		let metadata = self.metadata.as_ref().unwrap();
+		if let GMAMetadata::Standard { .. } = metadata {
+			let json = serde_json::ser::to_string_pretty(metadata).unwrap();
+			w.append("addon.json", json.len() as u64, timestamp, json.as_bytes())?;
+		}
+
+		let total_size_f = entries.unique().map(|entry| entry.size).sum::<u64>().max(1) as f64;
+		let mut written: u64 = 0;
+
+		for entry in entries.unique() {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
+
+			handle
+				.seek(SeekFrom::Start(entry.offset))
+				.map_err(|error| GMAError::entry_data(error, entry))?;
+			w.append(&entry.path, entry.size, timestamp, &mut **handle)
+				.map_err(|error| error.with_entry(entry.path.as_str()))?;
+
+			written += entry.size;
+			transaction.progress(written as f64 / total_size_f);
+		}
+
+		w.finish()
+	}
+
+	/// Packs the contents of the zip at `zip_path` into this GMA, through the same whitelist and ignore globs as `GMAFile::create`.
+	///
+	/// If this GMA has no metadata, the addon.json in the zip is used. Zips that have everything inside a single folder are handled too.
+	pub fn create_from_zip<P: AsRef<Path>>(&self, zip_path: P, transaction: Transaction, options: GMACreateOptions) -> Result<(), GMAError> {
+		main_thread_forbidden!();
+
+		let mut zip = zip::ZipArchive::new(BufReader::new(File::open(zip_path)?))?;
+
+		// The shallowest addon.json tells us where the addon's root is
+		let mut root: Option<(String, usize)> = None;
+		for i in 0..zip.len() {
+			let file = zip.by_index_raw(i)?;
+			let name = file.name().replace('\\', "/");
+			if let Some(prefix) = name.strip_suffix("addon.json") {
+				if (prefix.is_empty() || prefix.ends_with('/')) && root.as_ref().map(|(root, _)| prefix.len() < root.len()).unwrap_or(true) {
+					root = Some((prefix.to_owned(), i));
+				}
+			}
+		}
+
+		let metadata = match (&self.metadata, &root) {
+			(Some(metadata), _) => metadata.clone(),
+			(None, Some((_, i))) => {
+				let mut json = String::new();
+				zip.by_index(*i)?.read_to_string(&mut json)?;
+				serde_json::de::from_str(&json).map_err(|_| GMAError::AddonJsonMissing)?
+			}
+			(None, None) => return Err(GMAError::AddonJsonMissing),
+		};
+		let root = root.map(|(root, _)| root).unwrap_or_default();
+
+		let ignore = ignore_globs(metadata.ignore().map(|ignore| ignore.as_slice()));
+
+		// Paths are normalised and checked the same way as `GMABuilder::add`, and kept in the same order as `GMAFile::create`
+		let mut entries: BTreeMap<String, (u64, usize, String)> = BTreeMap::new();
+		for i in 0..zip.len() {
+			let file = zip.by_index_raw(i)?;
+			if file.is_dir() {
+				continue;
+			}
+
+			let name = file.name().replace('\\', "/");
+			let relative_path = match name.strip_prefix(&root) {
+				Some(relative_path) => GMABuilder::normalize_path(relative_path),
+				None => continue,
+			};
+			if relative_path == "addon.json" {
+				continue;
+			}
+
+			if sanitize::check_entry_path(&relative_path).is_err() || !source_path_allowed(&relative_path, ignore.as_deref(), &transaction) {
+				continue;
+			}
+
+			// Paths inside GMAs are lowercase, so files whose names only differ in case collide
+			if let Some((_, _, existing)) = entries.get(&relative_path) {
+				transaction.error("ERR_DUPLICATE_ENTRIES", format!("{}: {}, {}", relative_path, existing, file.name()));
+				return Err(GMAError::entry_exists(relative_path));
+			}
+
+			entries.insert(relative_path, (file.size(), i, file.name().to_owned()));
+		}
+
+		let total_size_f = entries.values().map(|(size, _, _)| *size).sum::<u64>().max(1) as f64;
+
+		let header = options.header(&metadata, &self.header.clone().unwrap_or_default())?;
+		let mut w = GMAWriter::new(self.write()?, header, entries.iter().map(|(path, (size, _, _))| (path.as_bytes(), *size)))?;
+
+		let mut written: u64 = 0;
+		for (path, (size, i, _)) in entries.iter() {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
+
+			// The writer reads the file to the end to check it's the size the zip says, which also makes the zip check the file's CRC
+			let mut file = zip.by_index(*i)?;
+			w.write_entry(&mut file)
+				.map_err(|error| GMAError::from(error).with_entry(path.as_str()))?;
+
+			written += size;
+			transaction.progress(written as f64 / total_size_f);
+		}
+
+		w.finish()?;
+
+		Ok(())
+	}
+}
+
+#[test]
+fn test_create_from_zip() {
+	let dir = super::test_dir("create_from_zip");
+	let zip_path = |name: &str, files: &[(&str, &str)]| {
+		let zip_path = dir.join(name);
+		let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+		for (path, contents) in files {
+			zip.start_file(*path, zip::write::FileOptions::default()).unwrap();
+			zip.write_all(contents.as_bytes()).unwrap();
+		}
+		zip.finish().unwrap();
+		zip_path
+	};
+	let create = |zip_path: &Path| {
+		let gma = GMAFile::new(zip_path.with_extension("gma"), None);
+		gma.create_from_zip(zip_path, transaction!(), GMACreateOptions::deterministic())
+			.map(|_| GMAFile::open(&gma.path).unwrap())
+	};
+
+	// Everything inside a single folder, in no particular order, with a file that isn't whitelisted
+	let good = create(&zip_path(
+		"good.zip",
+		&[
+			("addon/lua/autorun/b.lua", "print('b')"),
+			("addon/addon.json", r#"{"title": "Zipped", "type": "tool", "tags": []}"#),
+			("addon/Lua\\Autorun\\A.lua", "print('a')"),
+			("addon/virus.exe", "bad"),
+		],
+	));
+
+	// Files whose names only differ in case would be packed to the same path
+	let collision = create(&zip_path(
+		"collision.zip",
+		&[
+			("addon.json", r#"{"title": "Zipped", "type": "tool", "tags": []}"#),
+			("lua/autorun/a.lua", "print('a')"),
+			("LUA/autorun/A.lua", "print('A')"),
+		],
+	));
+
+	let mut good = good.unwrap();
+	good.entries().unwrap();
+	let report = good.verify(&transaction!()).unwrap();
+	let contents = good
+		.entries
+		.as_ref()
+		.unwrap()
+		.iter()
+		.map(|entry| {
+			let mut contents = String::new();
+			good.open_entry(&entry.path).unwrap().read_to_string(&mut contents).unwrap();
+			(entry.path.clone(), contents)
+		})
+		.collect::<Vec<_>>();
+
+	fs::remove_dir_all(&dir).ok();
+
+	assert_eq!(good.metadata.as_ref().unwrap().title(), "Zipped");
+	assert!(report.ok);
+	assert_eq!(
+		contents,
+		[
+			("lua/autorun/a.lua".to_string(), "print('a')".to_string()),
+			("lua/autorun/b.lua".to_string(), "print('b')".to_string()),
+		]
+	);
+
+	match collision {
+		Err(GMAError::EntryExists { entry }) => assert_eq!(entry.as_deref(), Some("lua/autorun/a.lua")),
+		_ => panic!("Expected the case collision to fail"),
+	}
+}
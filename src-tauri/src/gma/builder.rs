@@ -0,0 +1,349 @@
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{BufReader, BufWriter, Read, Seek, Write},
+	path::{Path, PathBuf},
+};
+
+use crate::transactions::Transaction;
+
+use super::{
+	diff::crc32_file, sanitize, whitelist, write::source_entries, GMACreateOptions, GMAEntry, GMAError, GMAFile, GMAHeader, GMAMetadata, GMAWriter,
+};
+
+pub enum GMABuilderSource<'a> {
+	Bytes(Vec<u8>),
+	/// Must provide exactly as many bytes as the size it was added with
+	Reader(Box<dyn Read + 'a>),
+	File(PathBuf),
+	/// An entry of another GMA, which is only opened once it's written
+	Entry(&'a GMAFile, String),
+}
+
+struct BuilderEntry<'a> {
+	size: u64,
+	/// Path bytes to write instead of the UTF-8 path, for entries copied from GMAs whose paths aren't UTF-8
+	raw_path: Option<Vec<u8>>,
+	source: GMABuilderSource<'a>,
+}
+
+/// Builds a GMA from entries that can come from anywhere, without staging them in a directory first.
+///
+/// Entries are written in path order, the same as `GMAFile::create`.
+pub struct GMABuilder<'a> {
+	metadata: GMAMetadata,
+	header: GMAHeader,
+	options: GMACreateOptions,
+	entries: BTreeMap<String, BuilderEntry<'a>>,
+}
+impl<'a> GMABuilder<'a> {
+	pub fn new(metadata: GMAMetadata) -> Self {
+		Self {
+			metadata,
+			header: GMAHeader::default(),
+			options: GMACreateOptions::default(),
+			entries: BTreeMap::new(),
+		}
+	}
+
+	/// Sets the steamid, author, required content and addon version. The timestamp comes from the options.
+	pub fn header(&mut self, header: GMAHeader) -> &mut Self {
+		self.header = header;
+		self
+	}
+
+	pub fn options(&mut self, options: GMACreateOptions) -> &mut Self {
+		self.options = options;
+		self
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn contains(&self, path: &str) -> bool {
+		self.entries.contains_key(&Self::normalize_path(path))
+	}
+
+	pub(crate) fn normalize_path(path: &str) -> String {
+		path.replace('\\', "/").trim_matches('/').to_lowercase()
+	}
+
+	/// Adds an entry at `path` inside the GMA, which must pass the whitelist
+	pub fn add<S: AsRef<str>>(&mut self, path: S, size: u64, source: GMABuilderSource<'a>) -> Result<&mut Self, GMAError> {
+		self.add_raw(path.as_ref(), None, size, source)
+	}
+
+	/// Like `add`, but writes `raw_path` instead of `path` if it's set, for entries whose paths aren't UTF-8
+	pub(crate) fn add_raw(&mut self, path: &str, raw_path: Option<Vec<u8>>, size: u64, source: GMABuilderSource<'a>) -> Result<&mut Self, GMAError> {
+		let path = Self::normalize_path(path);
+		if sanitize::check_entry_path(&path).is_err() {
+			return Err(GMAError::UnsafePath);
+		}
+		if !whitelist::check(&path) {
+			return Err(GMAError::NotWhitelisted);
+		}
+		if self.entries.contains_key(&path) {
+			return Err(GMAError::entry_exists(path));
+		}
+		self.entries.insert(path, BuilderEntry { size, raw_path, source });
+		Ok(self)
+	}
+
+	pub fn add_bytes<S: AsRef<str>>(&mut self, path: S, bytes: Vec<u8>) -> Result<&mut Self, GMAError> {
+		self.add(path, bytes.len() as u64, GMABuilderSource::Bytes(bytes))
+	}
+
+	/// `r` is read from when the GMA is written, and must provide `size` bytes
+	pub fn add_reader<S: AsRef<str>, R: Read + 'a>(&mut self, path: S, size: u64, r: R) -> Result<&mut Self, GMAError> {
+		self.add(path, size, GMABuilderSource::Reader(Box::new(r)))
+	}
+
+	/// Adds the file at `src` as `path` inside the GMA. The file is only read when the GMA is written.
+	pub fn add_file<S: AsRef<str>, P: Into<PathBuf>>(&mut self, path: S, src: P) -> Result<&mut Self, GMAError> {
+		let src = src.into();
+		let size = src.metadata()?.len();
+		self.add(path, size, GMABuilderSource::File(src))
+	}
+
+	/// Copies `entry` of `gma` into this GMA at the same path, keeping its raw path bytes if it has any
+	pub fn add_entry(&mut self, gma: &'a GMAFile, entry: &GMAEntry) -> Result<&mut Self, GMAError> {
+		self.add_raw(
+			&entry.path,
+			entry.raw_path.clone(),
+			entry.size,
+			GMABuilderSource::Entry(gma, entry.path.clone()),
+		)
+	}
+
+	/// Adds every file in `src_path` that would be packed by `GMAFile::create`, applying the whitelist and the metadata's ignore globs.
+	/// Files that aren't whitelisted are reported to the transaction and skipped.
+	///
+	/// Paths inside GMAs are lowercase, so files whose names only differ in case collide, which fails with both of their paths reported to the transaction.
+	pub fn add_dir<P: AsRef<Path>>(&mut self, src_path: P, transaction: &Transaction) -> Result<&mut Self, GMAError> {
+		let ignore = self.metadata.ignore().map(|ignore| ignore.as_slice());
+		for entry in source_entries(src_path.as_ref(), ignore, transaction)? {
+			if let Some(existing) = self.entries.get(&entry.relative_path) {
+				let mut sources = vec![entry.path.display().to_string()];
+				if let GMABuilderSource::File(existing) = &existing.source {
+					sources.insert(0, existing.display().to_string());
+				}
+				transaction.error("ERR_DUPLICATE_ENTRIES", format!("{}: {}", entry.relative_path, sources.join(", ")));
+				return Err(GMAError::entry_exists(entry.relative_path));
+			}
+			self.entries.insert(
+				entry.relative_path,
+				BuilderEntry {
+					size: entry.size,
+					raw_path: None,
+					source: GMABuilderSource::File(entry.path),
+				},
+			);
+		}
+		Ok(self)
+	}
+
+	pub fn write_to<W: Write + Seek>(self, w: W, transaction: &Transaction) -> Result<W, GMAError> {
+		let header = self.options.header(&self.metadata, &self.header)?;
+
+		let mut w = GMAWriter::new(
+			w,
+			header,
+			self.entries
+				.iter()
+				.map(|(path, entry)| (entry.raw_path.as_deref().unwrap_or(path.as_bytes()), entry.size)),
+		)?;
+
+		Self::write_entries(self.entries, &mut w, transaction)?;
+
+		Ok(w.finish()?)
+	}
+
+	/// Writes the GMA to a writer that can't seek, like stdout, without keeping it in memory.
+	///
+	/// Every entry is read twice, once to compute its CRC and once to write it, so entries added with `add_reader` can't be written this way.
+	pub fn write_to_stream<W: Write>(self, w: W, transaction: &Transaction) -> Result<W, GMAError> {
+		let header = self.options.header(&self.metadata, &self.header)?;
+
+		let mut crcs = Vec::with_capacity(self.entries.len());
+		for (path, entry) in self.entries.iter() {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
+
+			let crc = match &entry.source {
+				GMABuilderSource::Bytes(bytes) => crc32fast::hash(bytes),
+				GMABuilderSource::Reader(_) => {
+					return Err(
+						GMAError::from(std::io::Error::new(std::io::ErrorKind::InvalidInput, "readers can only be read once"))
+							.with_entry(path.as_str()),
+					)
+				}
+				GMABuilderSource::File(src) => match crc32_file(src) {
+					Ok((_, crc)) => crc,
+					Err(error) => {
+						transaction.error("ERR_PATH_IO_ERROR", src.to_owned());
+						return Err(GMAError::from(error).with_entry(path.as_str()));
+					}
+				},
+				GMABuilderSource::Entry(gma, entry_path) => {
+					gma.entries
+						.as_ref()
+						.and_then(|entries| entries.get_path(entry_path))
+						.ok_or(GMAError::EntryNotFound)?
+						.crc
+				}
+			};
+			crcs.push(crc);
+		}
+
+		let mut w = GMAWriter::new_streamed(
+			w,
+			header,
+			self.entries
+				.iter()
+				.zip(crcs)
+				.map(|((path, entry), crc)| (entry.raw_path.as_deref().unwrap_or(path.as_bytes()), entry.size, crc)),
+		)?;
+
+		Self::write_entries(self.entries, &mut w, transaction)?;
+
+		Ok(w.finish_streamed()?)
+	}
+
+	fn write_entries<W: Write>(entries: BTreeMap<String, BuilderEntry<'a>>, w: &mut GMAWriter<W>, transaction: &Transaction) -> Result<(), GMAError> {
+		let total_size: u64 = entries.values().map(|entry| entry.size).sum();
+		let total_size_f = total_size.max(1) as f64;
+		let mut written: u64 = 0;
+
+		for (path, entry) in entries {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
+
+			let result = match entry.source {
+				GMABuilderSource::Bytes(bytes) => w.write_entry(&mut bytes.as_slice()),
+				GMABuilderSource::Reader(mut r) => w.write_entry(&mut r),
+				GMABuilderSource::File(src) => match File::open(&src).and_then(|f| w.write_entry(&mut BufReader::new(f))) {
+					Ok(crc) => Ok(crc),
+					Err(error) => {
+						transaction.error("ERR_PATH_IO_ERROR", src);
+						Err(error)
+					}
+				},
+				GMABuilderSource::Entry(gma, entry_path) => {
+					let mut r = gma.open_entry(&entry_path).map_err(|error| error.with_entry(path.as_str()))?;
+					w.write_entry(&mut r)
+				}
+			};
+			if let Err(error) = result {
+				return Err(GMAError::from(error).with_entry(path));
+			}
+
+			written += entry.size;
+			transaction.progress(written as f64 / total_size_f);
+		}
+
+		Ok(())
+	}
+
+	pub fn write_to_path<P: AsRef<Path>>(self, path: P, transaction: &Transaction) -> Result<(), GMAError> {
+		self.write_to(BufWriter::new(File::create(path)?), transaction)?;
+		Ok(())
+	}
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_add_dir_case_collision() {
+	let src_path = super::test_dir("case_collision");
+	std::fs::create_dir_all(src_path.join("lua/autorun")).unwrap();
+	std::fs::write(src_path.join("lua/autorun/Foo.lua"), "print('Foo')").unwrap();
+	std::fs::write(src_path.join("lua/autorun/foo.lua"), "print('foo')").unwrap();
+
+	let result = GMABuilder::new(super::test_metadata()).add_dir(&src_path, &transaction!()).map(|_| ());
+
+	std::fs::remove_dir_all(&src_path).ok();
+
+	match result {
+		Err(GMAError::EntryExists { entry }) => assert_eq!(entry.as_deref(), Some("lua/autorun/foo.lua")),
+		_ => panic!("Expected the case collision to fail"),
+	}
+}
+
+#[test]
+fn test_write_roundtrip() {
+	let dir = super::test_dir("write_roundtrip");
+	let file_path = dir.join("file.lua");
+	std::fs::write(&file_path, "print('file')").unwrap();
+
+	let contents: [(&str, &[u8]); 3] = [
+		("lua/autorun/bytes.lua", b"print('bytes')"),
+		("lua/autorun/file.lua", b"print('file')"),
+		("lua/autorun/reader.lua", b"print('reader')"),
+	];
+
+	let mut builder = GMABuilder::new(super::test_metadata());
+	builder.add_reader("lua/autorun/reader.lua", 15, &b"print('reader')"[..]).unwrap();
+	builder.add_file("lua\\autorun\\File.lua", &file_path).unwrap();
+	builder.add_bytes("lua/autorun/bytes.lua", b"print('bytes')".to_vec()).unwrap();
+	let bytes = builder.write_to(std::io::Cursor::new(Vec::new()), &transaction!()).unwrap().into_inner();
+
+	std::fs::remove_dir_all(&dir).ok();
+
+	let mut gma = GMAFile::open_bytes(bytes, "roundtrip.gma").unwrap();
+	gma.entries().unwrap();
+	let entries = gma.entries.as_ref().unwrap().iter().collect::<Vec<_>>();
+	assert_eq!(
+		entries.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(),
+		contents.iter().map(|(path, _)| *path).collect::<Vec<_>>()
+	);
+
+	for (entry, (_, expected)) in entries.iter().zip(contents.iter()) {
+		assert_eq!(entry.size, expected.len() as u64);
+		assert_eq!(entry.crc, crc32fast::hash(expected));
+
+		let mut read = Vec::new();
+		gma.open_entry(&entry.path).unwrap().read_to_end(&mut read).unwrap();
+		assert_eq!(read, *expected);
+	}
+
+	let report = gma.verify(&transaction!()).unwrap();
+	assert!(report.ok);
+	assert_eq!(report.checksum, super::GMAChecksumStatus::Ok);
+}
+
+#[test]
+fn test_write_to_stream() {
+	let dir = super::test_dir("write_to_stream");
+	let file_path = dir.join("file.lua");
+	std::fs::write(&file_path, "print('file')").unwrap();
+
+	let builder = |with_reader: bool| {
+		let mut builder = GMABuilder::new(super::test_metadata());
+		builder.options(GMACreateOptions::deterministic());
+		builder.add_file("lua/autorun/file.lua", &file_path).unwrap();
+		builder.add_bytes("lua/autorun/bytes.lua", b"print('bytes')".to_vec()).unwrap();
+		if with_reader {
+			builder.add_reader("lua/autorun/reader.lua", 15, &b"print('reader')"[..]).unwrap();
+		}
+		builder
+	};
+
+	let seeked = builder(false)
+		.write_to(std::io::Cursor::new(Vec::new()), &transaction!())
+		.unwrap()
+		.into_inner();
+	// Vec<u8> can't seek
+	let streamed = builder(false).write_to_stream(Vec::new(), &transaction!()).unwrap();
+	let with_reader = builder(true).write_to_stream(Vec::new(), &transaction!());
+
+	std::fs::remove_dir_all(&dir).ok();
+
+	assert_eq!(streamed, seeked);
+	assert!(with_reader.is_err());
+}
@@ -0,0 +1,146 @@
+use std::{
+	fs::{self, File},
+	io::{BufWriter, SeekFrom, Write},
+	path::{Path, PathBuf},
+};
+
+use crate::transactions::Transaction;
+
+use super::{GMAError, GMAFile};
+
+/// The LZMA preset used unless another one is given
+pub const DEFAULT_LZMA_PRESET: u32 = 6;
+
+/// Counts the bytes written through it, so the compressed size is known even when the output is discarded
+struct CountingWriter<W: Write> {
+	inner: W,
+	written: u64,
+}
+impl<W: Write> Write for CountingWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.written += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+impl GMAFile {
+	/// Compresses this GMA into the LZMA container used by workshop .bin files, the inverse of `GMAFile::decompress`.
+	/// Returns the compressed size.
+	pub fn compress<W: Write>(&self, w: W, preset: u32, transaction: &Transaction) -> Result<u64, GMAError> {
+		main_thread_forbidden!();
+
+		let lzma_options = xz2::stream::LzmaOptions::new_preset(preset).map_err(|_| GMAError::LZMA)?;
+		let lzma_encoder = xz2::stream::Stream::new_lzma_encoder(&lzma_options).map_err(|err| {
+			eprintln!("LZMA error: {err:?}");
+			GMAError::LZMA
+		})?;
+
+		let mut xz_encoder = xz2::write::XzEncoder::new_stream(CountingWriter { inner: w, written: 0 }, lzma_encoder);
+
+		let mut handle = self.read()?;
+		let size = crate::stream_len(&mut *handle)?;
+		handle.seek(SeekFrom::Start(0))?;
+
+		let size_f = size.max(1) as f64;
+		let mut read_total: u64 = 0;
+		let mut last_progress = 0;
+
+		let mut buf = vec![0u8; 64 * 1024];
+		loop {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
+
+			let read = match handle.read(&mut buf) {
+				Ok(0) => break,
+				Ok(read) => read,
+				Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(err) => return Err(err.into()),
+			};
+
+			xz_encoder.write_all(&buf[..read])?;
+			read_total += read as u64;
+
+			let progress = read_total as f64 / size_f;
+			if (progress * 1000.) as u32 != last_progress {
+				last_progress = (progress * 1000.) as u32;
+				transaction.progress(progress);
+			}
+		}
+
+		let mut w = xz_encoder.finish()?;
+		w.flush()?;
+
+		Ok(w.written)
+	}
+
+	/// Compresses this GMA to `dest`, see `GMAFile::compress`
+	pub fn compress_to<P: AsRef<Path>>(&self, dest: P, preset: u32, transaction: &Transaction) -> Result<u64, GMAError> {
+		let dest = dest.as_ref();
+		let result = File::create(dest)
+			.map_err(GMAError::from)
+			.and_then(|f| self.compress(BufWriter::new(f), preset, transaction));
+
+		if result.is_err() {
+			fs::remove_file(dest).ok();
+		}
+
+		result
+	}
+
+	/// Compresses this GMA without keeping the output, to find out how big its download will be
+	pub fn compressed_size(&self, transaction: &Transaction) -> Result<u64, GMAError> {
+		self.compress(std::io::sink(), DEFAULT_LZMA_PRESET, transaction)
+	}
+}
+
+#[tauri::command]
+pub fn compress_gma(gma_path: PathBuf, dest: Option<PathBuf>) -> Option<u32> {
+	let gma = GMAFile::open(gma_path).ok()?;
+
+	let transaction = transaction!();
+	let id = transaction.id;
+
+	rayon::spawn(move || {
+		let result = match dest {
+			Some(dest) => gma.compress_to(dest, DEFAULT_LZMA_PRESET, &transaction),
+			None => gma.compressed_size(&transaction),
+		};
+
+		match result {
+			Ok(size) => transaction.finished(size),
+			Err(error) => {
+				if !transaction.aborted() {
+					transaction.error(error.to_string(), turbonone!());
+				}
+			}
+		}
+	});
+
+	Some(id)
+}
+
+#[test]
+fn test_compress_roundtrip() {
+	use std::io::Read;
+
+	let bytes = super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")]);
+	let gma = GMAFile::open_bytes(bytes.clone(), "compress.gma").unwrap();
+
+	let mut compressed = Vec::new();
+	let compressed_len = gma.compress(&mut compressed, DEFAULT_LZMA_PRESET, &transaction!()).unwrap();
+	assert_eq!(compressed_len, compressed.len() as u64);
+	assert_eq!(gma.compressed_size(&transaction!()).unwrap(), compressed_len);
+
+	// Workshop .bin files are the legacy LZMA format, not .xz
+	let mut decompressed = Vec::new();
+	xz2::read::XzDecoder::new_stream(&compressed[..], xz2::stream::Stream::new_lzma_decoder(u64::MAX).unwrap())
+		.read_to_end(&mut decompressed)
+		.unwrap();
+	assert_eq!(decompressed, bytes);
+}
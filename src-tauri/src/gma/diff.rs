@@ -0,0 +1,274 @@
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{BufReader, Read},
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Serialize;
+
+use crate::transactions::Transaction;
+
+use super::{write::source_entries, GMAError, GMAFile, GMAMetadata};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GMADiffEntry {
+	pub path: String,
+	pub size: u64,
+	pub crc: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GMADiffModified {
+	pub path: String,
+	pub old_size: u64,
+	pub new_size: u64,
+	pub old_crc: u32,
+	pub new_crc: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct GMADiff {
+	pub added: Vec<GMADiffEntry>,
+	pub removed: Vec<GMADiffEntry>,
+	pub modified: Vec<GMADiffModified>,
+	pub unchanged: usize,
+}
+impl GMADiff {
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+	}
+
+	fn compute(old: BTreeMap<String, (u64, u32)>, mut new: BTreeMap<String, (u64, u32)>) -> GMADiff {
+		let mut diff = GMADiff::default();
+
+		for (path, (old_size, old_crc)) in old {
+			match new.remove(&path) {
+				None => diff.removed.push(GMADiffEntry {
+					path,
+					size: old_size,
+					crc: old_crc,
+				}),
+
+				Some((new_size, new_crc)) if new_size != old_size || new_crc != old_crc => diff.modified.push(GMADiffModified {
+					path,
+					old_size,
+					new_size,
+					old_crc,
+					new_crc,
+				}),
+
+				Some(_) => diff.unchanged += 1,
+			}
+		}
+
+		diff.added = new.into_iter().map(|(path, (size, crc))| GMADiffEntry { path, size, crc }).collect();
+
+		diff
+	}
+}
+
+pub(crate) fn crc32_file(path: &Path) -> Result<(u64, u32), std::io::Error> {
+	let mut r = BufReader::new(File::open(path)?);
+
+	let mut crc32 = crc32fast::Hasher::new();
+	let mut size = 0;
+	let mut buf = [0u8; 8192];
+	loop {
+		match r.read(&mut buf) {
+			Ok(0) => break,
+			Ok(read) => {
+				crc32.update(&buf[..read]);
+				size += read as u64;
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok((size, crc32.finalize()))
+}
+
+impl GMAFile {
+	fn entries_by_path(&mut self) -> Result<BTreeMap<String, (u64, u32)>, GMAError> {
+		self.entries()?;
+		Ok(self
+			.entries
+			.as_ref()
+			.unwrap()
+			.unique()
+			.map(|entry| (entry.path.clone(), (entry.size, entry.crc)))
+			.collect())
+	}
+
+	/// Compares this GMA against a newer version of it
+	pub fn diff(&mut self, other: &mut GMAFile) -> Result<GMADiff, GMAError> {
+		main_thread_forbidden!();
+		Ok(GMADiff::compute(self.entries_by_path()?, other.entries_by_path()?))
+	}
+
+	/// Compares this GMA against a source directory, as if the directory was packed with `GMAFile::create`
+	pub fn diff_dir<P: AsRef<Path>>(&mut self, dir: P, transaction: &Transaction) -> Result<GMADiff, GMAError> {
+		main_thread_forbidden!();
+
+		let dir = dir.as_ref();
+		let old = self.entries_by_path()?;
+
+		let metadata = GMAMetadata::read_addon_json(dir);
+		let entries = source_entries(
+			dir,
+			metadata.as_ref().and_then(|metadata| metadata.ignore()).map(|ignore| ignore.as_slice()),
+			transaction,
+		)?;
+
+		let entries_len_f = entries.len() as f64;
+		let i = AtomicUsize::new(0);
+
+		let new = entries
+			.into_par_iter()
+			.map(|entry| {
+				if transaction.aborted() {
+					return Err(GMAError::Cancelled);
+				}
+
+				let (size, crc) = crc32_file(&entry.path)?;
+
+				let i = i.fetch_add(1, Ordering::AcqRel) + 1;
+				transaction.progress(i as f64 / entries_len_f);
+
+				Ok((entry.relative_path, (size, crc)))
+			})
+			.collect::<Result<BTreeMap<_, _>, GMAError>>()?;
+
+		Ok(GMADiff::compute(old, new))
+	}
+}
+
+#[tauri::command]
+pub fn diff_gma(gma_path: PathBuf, other_path: PathBuf) -> Option<u32> {
+	let mut gma = GMAFile::open(gma_path).ok()?;
+
+	let transaction = transaction!();
+	let id = transaction.id;
+
+	rayon::spawn(move || {
+		let result = if other_path.is_dir() {
+			gma.diff_dir(other_path, &transaction)
+		} else {
+			GMAFile::open(other_path).and_then(|mut other| gma.diff(&mut other))
+		};
+
+		match result {
+			Ok(diff) => transaction.finished(diff),
+			Err(error) => {
+				if !transaction.aborted() {
+					transaction.error(error.to_string(), turbonone!());
+				}
+			}
+		}
+	});
+
+	Some(id)
+}
+
+#[test]
+fn test_diff_compute() {
+	let old: BTreeMap<String, (u64, u32)> = [
+		("lua/autorun/a.lua".to_string(), (10, 1)),
+		("lua/autorun/b.lua".to_string(), (20, 2)),
+		("materials/c.vmt".to_string(), (30, 3)),
+	]
+	.into_iter()
+	.collect();
+
+	let new: BTreeMap<String, (u64, u32)> = [
+		("lua/autorun/a.lua".to_string(), (10, 1)),
+		("lua/autorun/b.lua".to_string(), (20, 4)),
+		("sound/d.wav".to_string(), (40, 5)),
+	]
+	.into_iter()
+	.collect();
+
+	let diff = GMADiff::compute(old, new);
+	assert_eq!(diff.unchanged, 1);
+	assert_eq!(
+		diff.removed.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(),
+		["materials/c.vmt"]
+	);
+	assert_eq!(diff.added.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(), ["sound/d.wav"]);
+	assert_eq!(diff.modified.len(), 1);
+	assert_eq!(diff.modified[0].path, "lua/autorun/b.lua");
+	assert_eq!((diff.modified[0].old_crc, diff.modified[0].new_crc), (2, 4));
+	assert!(!diff.is_empty());
+}
+
+#[test]
+fn test_diff_gma() {
+	let old_entries: &[(&str, &[u8])] = &[
+		("lua/autorun/a.lua", b"print('a')"),
+		("lua/autorun/b.lua", b"print('b')"),
+		("materials/c.vmt", b"\"VertexLitGeneric\" {}"),
+	];
+	let old = super::test_gma(old_entries);
+	let new = super::test_gma(&[
+		("lua/autorun/a.lua", b"print('a')"),
+		("lua/autorun/b.lua", b"print('b2')"),
+		("sound/d.wav", b"RIFF"),
+	]);
+
+	let mut old = GMAFile::open_bytes(old, "old.gma").unwrap();
+	let mut new = GMAFile::open_bytes(new, "new.gma").unwrap();
+	let diff = old.diff(&mut new).unwrap();
+
+	assert_eq!(diff.unchanged, 1);
+	assert_eq!(
+		diff.removed.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(),
+		["materials/c.vmt"]
+	);
+	assert_eq!(diff.added.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(), ["sound/d.wav"]);
+	assert_eq!(diff.added[0].crc, crc32fast::hash(b"RIFF"));
+	assert_eq!(diff.modified.len(), 1);
+	assert_eq!(diff.modified[0].path, "lua/autorun/b.lua");
+	assert_eq!((diff.modified[0].old_size, diff.modified[0].new_size), (10, 11));
+	assert_eq!(diff.modified[0].new_crc, crc32fast::hash(b"print('b2')"));
+
+	let mut same = GMAFile::open_bytes(super::test_gma(old_entries), "same.gma").unwrap();
+	assert!(old.diff(&mut same).unwrap().is_empty());
+}
+
+#[test]
+fn test_diff_dir() {
+	let bytes = super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")]);
+
+	let dir = super::test_dir("diff_dir");
+	std::fs::create_dir_all(dir.join("lua/autorun")).unwrap();
+	std::fs::write(dir.join("lua/autorun/a.lua"), "print('a')").unwrap();
+	std::fs::write(dir.join("lua/autorun/c.lua"), "print('c')").unwrap();
+	std::fs::write(dir.join("lua/autorun/ignored.lua"), "print('ignored')").unwrap();
+	// Not whitelisted, so it wouldn't be packed either
+	std::fs::write(dir.join("virus.exe"), "bad").unwrap();
+	std::fs::write(
+		dir.join("addon.json"),
+		r#"{"title": "Test", "type": "tool", "tags": [], "ignore": ["lua/autorun/ignored.lua"]}"#,
+	)
+	.unwrap();
+
+	let diff = GMAFile::open_bytes(bytes, "diff.gma").unwrap().diff_dir(&dir, &transaction!());
+
+	std::fs::remove_dir_all(&dir).ok();
+
+	let diff = diff.unwrap();
+	assert_eq!(diff.unchanged, 1);
+	assert_eq!(
+		diff.removed.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(),
+		["lua/autorun/b.lua"]
+	);
+	assert_eq!(
+		diff.added.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(),
+		["lua/autorun/c.lua"]
+	);
+	assert!(diff.modified.is_empty());
+}
@@ -0,0 +1,316 @@
+use std::{
+	collections::BTreeMap,
+	fs::{self, File},
+	io::{BufReader, BufWriter, Read, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use crate::transactions::Transaction;
+
+use super::{sanitize, whitelist, GMABuilder, GMAEntry, GMAError, GMAFile, GMAWriter};
+
+pub enum GMAEditSource {
+	File(PathBuf),
+	Bytes(Vec<u8>),
+}
+impl GMAEditSource {
+	fn size(&self) -> Result<u64, std::io::Error> {
+		match self {
+			GMAEditSource::File(path) => Ok(path.metadata()?.len()),
+			GMAEditSource::Bytes(bytes) => Ok(bytes.len() as u64),
+		}
+	}
+
+	fn open(&self) -> Result<Box<dyn Read + '_>, std::io::Error> {
+		match self {
+			GMAEditSource::File(path) => Ok(Box::new(BufReader::new(File::open(path)?))),
+			GMAEditSource::Bytes(bytes) => Ok(Box::new(bytes.as_slice())),
+		}
+	}
+}
+
+enum PlannedEntry<'a> {
+	Copy(&'a GMAEntry),
+	Edit(&'a GMAEditSource),
+}
+
+/// Adds, replaces and removes entries of an existing GMA.
+///
+/// Unchanged entries are copied straight from the source GMA, so only the entries list and CRCs are rebuilt.
+pub struct GMAEditor<'a> {
+	gma: &'a GMAFile,
+
+	/// `None` removes the entry
+	changes: BTreeMap<String, Option<GMAEditSource>>,
+}
+impl<'a> GMAEditor<'a> {
+	pub fn new(gma: &'a GMAFile) -> Self {
+		debug_assert!(gma.entries.is_some(), "Expected entries to be read by this point");
+		Self {
+			gma,
+			changes: BTreeMap::new(),
+		}
+	}
+
+	/// Normalizes `path` the same way the builder does, and refuses paths that would escape the extraction directory
+	fn entry_path(path: &str) -> Result<String, GMAError> {
+		let path = GMABuilder::normalize_path(path);
+		if sanitize::check_entry_path(&path).is_err() {
+			return Err(GMAError::UnsafePath);
+		}
+		Ok(path)
+	}
+
+	fn exists_in_gma(&self, path: &str) -> bool {
+		self.gma.entries.as_ref().map(|entries| entries.contains_path(path)).unwrap_or(false)
+	}
+
+	pub fn contains(&self, path: &str) -> bool {
+		match self.changes.get(path) {
+			Some(change) => change.is_some(),
+			None => self.exists_in_gma(path),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.changes.is_empty()
+	}
+
+	pub fn add<S: AsRef<str>>(&mut self, path: S, source: GMAEditSource) -> Result<&mut Self, GMAError> {
+		let path = Self::entry_path(path.as_ref())?;
+		if !whitelist::check(&path) {
+			return Err(GMAError::NotWhitelisted);
+		}
+		if self.contains(&path) {
+			return Err(GMAError::entry_exists(path));
+		}
+		self.changes.insert(path, Some(source));
+		Ok(self)
+	}
+
+	pub fn replace<S: AsRef<str>>(&mut self, path: S, source: GMAEditSource) -> Result<&mut Self, GMAError> {
+		let path = Self::entry_path(path.as_ref())?;
+		if !self.contains(&path) {
+			return Err(GMAError::EntryNotFound);
+		}
+		self.changes.insert(path, Some(source));
+		Ok(self)
+	}
+
+	pub fn remove<S: AsRef<str>>(&mut self, path: S) -> Result<&mut Self, GMAError> {
+		let path = GMABuilder::normalize_path(path.as_ref());
+		if !self.contains(&path) {
+			return Err(GMAError::EntryNotFound);
+		}
+		if self.exists_in_gma(&path) {
+			self.changes.insert(path, None);
+		} else {
+			// Just undo the pending addition
+			self.changes.remove(&path);
+		}
+		Ok(self)
+	}
+
+	/// Writes the edited GMA to `dest`, which must not be the path of the GMA being edited
+	pub fn write_to<P: AsRef<Path>>(&self, dest: P, transaction: &Transaction) -> Result<(), GMAError> {
+		main_thread_forbidden!();
+
+		let entries = self.gma.entries.as_ref().expect("Expected entries to be read by this point");
+		entries.ensure_none_rejected()?;
+
+		// Existing entries keep their position, new entries are appended
+		let mut plan = Vec::with_capacity(entries.len());
+		for entry in entries.unique() {
+			match self.changes.get(&entry.path) {
+				None => plan.push((entry.path_bytes(), entry.size, PlannedEntry::Copy(entry))),
+				Some(Some(source)) => plan.push((entry.path_bytes(), source.size()?, PlannedEntry::Edit(source))),
+				Some(None) => {}
+			}
+		}
+		for (path, change) in self.changes.iter() {
+			if let Some(source) = change {
+				if !entries.contains_path(path) {
+					plan.push((path.as_bytes(), source.size()?, PlannedEntry::Edit(source)));
+				}
+			}
+		}
+
+		let mut handle = self.gma.read()?;
+
+		// The header and metadata are copied as-is
+		let mut header = vec![0u8; self.gma.pointers.entries_list as usize];
+		handle.seek(SeekFrom::Start(0))?;
+		handle.read_exact(&mut header)?;
+
+		let mut w = GMAWriter::new(
+			BufWriter::new(File::create(dest.as_ref())?),
+			header,
+			plan.iter().map(|(path, size, _)| (*path, *size)),
+		)?;
+
+		let plan_len_f = plan.len().max(1) as f64;
+		for (i, (_, _, entry)) in plan.iter().enumerate() {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
+
+			match entry {
+				PlannedEntry::Copy(entry) => {
+					handle.seek(SeekFrom::Start(entry.offset))?;
+					w.write_entry(&mut (&mut *handle).take(entry.size))?;
+				}
+				PlannedEntry::Edit(source) => {
+					w.write_entry(&mut *source.open()?)?;
+				}
+			}
+
+			transaction.progress((i + 1) as f64 / plan_len_f);
+		}
+
+		// Unmap the source GMA before save() renames over it, Windows refuses to replace a file that's still mapped
+		drop(handle);
+
+		w.finish()?;
+
+		Ok(())
+	}
+
+	/// Writes the edited GMA next to the original, then replaces the original with it
+	///
+	/// `self.gma` describes the original afterwards, so it must be opened again before reading it.
+	pub fn save(&self, transaction: &Transaction) -> Result<(), GMAError> {
+		let mut tmp_path = self.gma.path.clone().into_os_string();
+		tmp_path.push(".tmp");
+		let tmp_path = PathBuf::from(tmp_path);
+
+		if let Err(error) = self.write_to(&tmp_path, transaction) {
+			fs::remove_file(&tmp_path).ok();
+			return Err(error);
+		}
+
+		fs::rename(&tmp_path, &self.gma.path)?;
+
+		Ok(())
+	}
+}
+
+#[test]
+fn test_edit() {
+	use super::GMAChecksumStatus;
+
+	let bytes = super::test_gma(&[
+		("lua/autorun/a.lua", b"print('a')"),
+		("lua/autorun/b.lua", b"print('b')"),
+		("lua/autorun/c.lua", b"print('c')"),
+	]);
+
+	let dir = super::test_dir("edit");
+	let path = dir.join("edit.gma");
+	fs::write(&path, &bytes).unwrap();
+
+	let read = |path: &Path| {
+		let mut gma = GMAFile::open(path).unwrap();
+		gma.entries().unwrap();
+		let report = gma.verify(&transaction!()).unwrap();
+		let contents = gma
+			.entries
+			.as_ref()
+			.unwrap()
+			.iter()
+			.map(|entry| {
+				let mut contents = String::new();
+				gma.open_entry(&entry.path).unwrap().read_to_string(&mut contents).unwrap();
+				(entry.path.clone(), contents)
+			})
+			.collect::<Vec<_>>();
+		(report, contents)
+	};
+
+	let edited = {
+		let mut gma = GMAFile::open(&path).unwrap();
+		gma.entries().unwrap();
+
+		let mut editor = GMAEditor::new(&gma);
+		editor
+			.replace("lua/autorun/b.lua", GMAEditSource::Bytes(b"print('b2')".to_vec()))
+			.unwrap()
+			.remove("lua/autorun/c.lua")
+			.unwrap()
+			.add("LUA\\autorun\\d.lua", GMAEditSource::Bytes(b"print('d')".to_vec()))
+			.unwrap();
+
+		assert!(matches!(
+			editor.add("lua/autorun/a.lua", GMAEditSource::Bytes(Vec::new())),
+			Err(GMAError::EntryExists { .. })
+		));
+		assert!(matches!(editor.remove("lua/autorun/c.lua"), Err(GMAError::EntryNotFound)));
+		assert!(matches!(
+			editor.add("lua/../evil.lua", GMAEditSource::Bytes(Vec::new())),
+			Err(GMAError::UnsafePath)
+		));
+
+		let edited = dir.join("edited.gma");
+		editor.write_to(&edited, &transaction!()).unwrap();
+		edited
+	};
+	let (edited_report, edited_contents) = read(&edited);
+
+	// A source that can't be read fails the save partway through writing, which must leave the original alone
+	let failed = {
+		let mut gma = GMAFile::open(&path).unwrap();
+		gma.entries().unwrap();
+
+		let mut editor = GMAEditor::new(&gma);
+		editor.replace("lua/autorun/a.lua", GMAEditSource::File(dir.clone())).unwrap();
+		editor.save(&transaction!())
+	};
+	let original = fs::read(&path).unwrap();
+	let leftovers = fs::read_dir(&dir).unwrap().count();
+
+	fs::remove_dir_all(&dir).ok();
+
+	assert!(edited_report.ok);
+	assert_eq!(edited_report.checksum, GMAChecksumStatus::Ok);
+	assert_eq!(
+		edited_contents,
+		[
+			("lua/autorun/a.lua".to_string(), "print('a')".to_string()),
+			("lua/autorun/b.lua".to_string(), "print('b2')".to_string()),
+			("lua/autorun/d.lua".to_string(), "print('d')".to_string()),
+		]
+	);
+
+	assert!(failed.is_err());
+	assert_eq!(original, bytes);
+	// edit.gma and edited.gma, without the temporary file the save wrote to
+	assert_eq!(leftovers, 2);
+}
+
+#[test]
+fn test_save_mapped() {
+	let dir = super::test_dir("edit_save");
+	let path = dir.join("save.gma");
+	fs::write(&path, super::test_gma(&[("lua/autorun/a.lua", b"print('a')")])).unwrap();
+
+	// Opened from disk rather than a buffer, so the editor reads it through a memory map
+	let mut gma = GMAFile::open(&path).unwrap();
+	gma.entries().unwrap();
+	assert!(matches!(gma.read().unwrap(), super::GMAReader::Mmap(_)));
+
+	let mut editor = GMAEditor::new(&gma);
+	editor.add("lua/autorun/b.lua", GMAEditSource::Bytes(b"print('b')".to_vec())).unwrap();
+	let saved = editor.save(&transaction!());
+
+	let mut reopened = GMAFile::open(&path).unwrap();
+	reopened.entries().unwrap();
+	let mut contents = String::new();
+	reopened.open_entry("lua/autorun/b.lua").unwrap().read_to_string(&mut contents).unwrap();
+	let entries = reopened.entries.as_ref().unwrap().len();
+	drop(reopened);
+
+	fs::remove_dir_all(&dir).ok();
+
+	saved.unwrap();
+	assert_eq!(entries, 2);
+	assert_eq!(contents, "print('b')");
+}
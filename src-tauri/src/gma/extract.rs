@@ -1,23 +1,23 @@
 use std::{
+	collections::HashSet,
 	fs::{self, File},
-	io::{BufWriter, Cursor, Read, SeekFrom},
+	io::{BufWriter, Read, SeekFrom},
 	path::{Path, PathBuf},
-	sync::{
-		atomic::{AtomicBool, AtomicUsize, Ordering},
-		Arc,
-	},
+	sync::atomic::{AtomicUsize, Ordering},
 };
 
 use crate::{app_data, transactions::Transaction};
 
-use super::{whitelist, GMAEntry, GMAError, GMAFile, GMAMetadata, GMAReader};
+use super::{diff::crc32_file, sanitize, whitelist, GMAEntry, GMAError, GMAFile, GMAMetadata, GMAReader, RejectedEntry};
 
 use lazy_static::lazy_static;
+use path_slash::PathExt;
 use rayon::{
 	iter::{IntoParallelRefIterator, ParallelIterator},
 	ThreadPool,
 };
 use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
 
 lazy_static! {
 	pub static ref THREAD_POOL: ThreadPool = thread_pool!();
@@ -29,6 +29,11 @@ pub enum ExtractionOverwriteMode {
 	#[default]
 	Recycle,
 	Delete,
+	/// Only writes files whose size or CRC differ from their entry in the GMA
+	Sync,
+	/// Like `Sync`, and also moves files that aren't in the GMA anymore to the recycle bin.
+	/// Only folders named after the addon are pruned, never a directory that was picked to extract into directly.
+	SyncPrune,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -43,6 +48,11 @@ pub enum ExtractDestination {
 	NamedDirectory(PathBuf),
 }
 impl ExtractDestination {
+	/// Whether the addon gets a folder of its own, named after it, which nothing but gmpublisher should be putting files in
+	fn is_owned(&self) -> bool {
+		!matches!(self, ExtractDestination::Directory(_))
+	}
+
 	fn prepare<S: AsRef<str>>(self, extracted_name: S) -> PathBuf {
 		use ExtractDestination::*;
 
@@ -51,7 +61,7 @@ impl ExtractDestination {
 			Some(path)
 		};
 
-		let recycle_existing = !matches!(self, Directory(_));
+		let recycle_existing = self.is_owned();
 
 		let mut path = match self {
 			Temp => None,
@@ -73,7 +83,7 @@ impl ExtractDestination {
 
 		if recycle_existing && path.exists() {
 			let success = match &app_data!().settings.read().extract_overwrite_mode {
-				ExtractionOverwriteMode::Overwrite => true,
+				ExtractionOverwriteMode::Overwrite | ExtractionOverwriteMode::Sync | ExtractionOverwriteMode::SyncPrune => true,
 				ExtractionOverwriteMode::Recycle => trash::delete(&path).is_ok(),
 				ExtractionOverwriteMode::Delete => fs::remove_dir_all(&path).is_ok(),
 			};
@@ -100,89 +110,200 @@ impl ExtractDestination {
 	}
 }
 
+/// Include and exclude globs for extracting only some of the entries of a GMA, matched like the whitelist.
+/// Patterns starting with `!` exclude entries. Without any include patterns, every entry that isn't excluded is extracted.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "Vec<String>")]
+pub struct ExtractFilter {
+	include: Vec<String>,
+	exclude: Vec<String>,
+}
+impl ExtractFilter {
+	pub fn new<I: IntoIterator<Item = S>, S: AsRef<str>>(patterns: I) -> Self {
+		let mut filter = ExtractFilter::default();
+		for pattern in patterns {
+			let pattern = pattern.as_ref().trim();
+			let (list, pattern) = match pattern.strip_prefix('!') {
+				Some(pattern) => (&mut filter.exclude, pattern),
+				None => (&mut filter.include, pattern),
+			};
+
+			let mut pattern = pattern.replace('\\', "/").to_lowercase();
+			if pattern.is_empty() {
+				continue;
+			}
+			pattern.push('\0');
+			list.push(pattern);
+		}
+		filter
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.include.is_empty() && self.exclude.is_empty()
+	}
+
+	pub fn matches(&self, path: &str) -> bool {
+		if self.is_empty() {
+			return true;
+		}
+
+		let mut path = path.to_lowercase();
+		path.push('\0');
+
+		(self.include.is_empty() || self.include.iter().any(|glob| whitelist::globber(glob, &path)))
+			&& !self.exclude.iter().any(|glob| whitelist::globber(glob, &path))
+	}
+}
+impl From<Vec<String>> for ExtractFilter {
+	fn from(patterns: Vec<String>) -> Self {
+		ExtractFilter::new(patterns)
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractFailure {
+	pub path: String,
+	/// The kind of IO error that occurred, e.g. `PermissionDenied`
+	pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractReport {
+	pub path: PathBuf,
+	pub extracted: Vec<String>,
+	/// Entries that were already on disk when syncing
+	pub unchanged: usize,
+	/// Files that were moved from the destination to the recycle bin because they aren't in the GMA anymore
+	pub deleted: usize,
+	/// Entries that weren't extracted because they aren't whitelisted
+	pub skipped: Vec<String>,
+	pub failed: Vec<ExtractFailure>,
+	/// Entries that weren't extracted because their paths are unsafe
+	pub rejected: Vec<RejectedEntry>,
+}
+
+/// Counts the bytes read through it, so decompression progress can be measured against the compressed size
+struct CountingReader<R: Read> {
+	inner: R,
+	read: u64,
+}
+impl<R: Read> Read for CountingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.read += read as u64;
+		Ok(read)
+	}
+}
+
 impl GMAFile {
+	/// Decompresses an LZMA compressed GMA, such as a legacy workshop .bin, to a new temporary GMA and opens it.
+	/// The caller should delete the temporary GMA once it's done with it.
 	pub fn decompress<P: AsRef<Path>>(path: P, transaction: Transaction) -> Result<GMAFile, GMAError> {
 		main_thread_forbidden!();
 
-		let input = File::open(path.as_ref())?;
+		let mut dir = app_data!().temp_dir().to_owned();
+		dir.push("decompressed");
+		fs::create_dir_all(&dir)?;
+
+		// Every workshop .bin is called the same thing, so each decompression gets a file of its own
+		let stem = path.as_ref().file_stem().unwrap_or_default().to_string_lossy().into_owned();
+		let mut i: u32 = 0;
+		let (dest, f) = loop {
+			let dest = dir.join(format!("{}_{}_{}.gma", stem, std::process::id(), i));
+			match fs::OpenOptions::new().write(true).create_new(true).open(&dest) {
+				Ok(f) => break (dest, f),
+				Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => i += 1,
+				Err(error) => return Err(error.into()),
+			}
+		};
 
-		let bytes_total = input.metadata().map(|metadata| metadata.len()).ok();
+		GMAFile::decompress_into(path.as_ref(), f, &dest, &transaction)
+	}
 
-		let lzma_decoder = xz2::stream::Stream::new_lzma_decoder(u64::MAX).map_err(|err| {
-			eprintln!("LZMA error: {err:?}");
-			GMAError::LZMA
-		})?;
+	/// Decompresses an LZMA compressed GMA to `dest` and opens it. The decompressed GMA is streamed to disk, never held in memory.
+	pub fn decompress_to<P: AsRef<Path>, D: AsRef<Path>>(path: P, dest: D, transaction: &Transaction) -> Result<GMAFile, GMAError> {
+		main_thread_forbidden!();
 
-		let mut xz_decoder = xz2::read::XzDecoder::new_stream(input, lzma_decoder);
+		let dest = dest.as_ref();
+		GMAFile::decompress_into(path.as_ref(), File::create(dest)?, dest, transaction)
+	}
 
-		let mut output = if let Some(ref bytes_total) = bytes_total {
-			Vec::with_capacity((*bytes_total * 5) as usize)
-		} else {
-			Vec::new()
-		};
+	/// Decompresses into `f`, which was created at `dest`, and opens it. Whatever was written is removed if that fails.
+	fn decompress_into(path: &Path, f: File, dest: &Path, transaction: &Transaction) -> Result<GMAFile, GMAError> {
+		let result = GMAFile::decompress_stream(path, f, transaction).and_then(|_| GMAFile::open(dest));
+		if result.is_err() {
+			fs::remove_file(dest).ok();
+		}
+		result
+	}
 
-		let result = if let Some(bytes_total) = bytes_total {
+	fn decompress_stream(path: &Path, f: File, transaction: &Transaction) -> Result<(), GMAError> {
+		use std::io::Write;
+
+		let input = File::open(path)?;
+
+		let bytes_total = input.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+		if bytes_total > 0 {
 			transaction.data((turbonone!(), bytes_total));
+		}
 
-			let bytes_total_f = bytes_total as f64;
+		let lzma_decoder = xz2::stream::Stream::new_lzma_decoder(u64::MAX).map_err(|err| {
+			eprintln!("LZMA error: {err:?}");
+			GMAError::LZMA
+		})?;
 
-			let complete = Arc::new(AtomicBool::new(false));
-			let complete_ref = complete.clone();
+		let mut xz_decoder = xz2::read::XzDecoder::new_stream(CountingReader { inner: input, read: 0 }, lzma_decoder);
 
-			struct StupidlyUnsafeProgressMonitorPtr<T: Read>(*mut xz2::read::XzDecoder<T>);
-			unsafe impl<T: Read> Sync for StupidlyUnsafeProgressMonitorPtr<T> {}
-			unsafe impl<T: Read> Send for StupidlyUnsafeProgressMonitorPtr<T> {}
+		let mut w = BufWriter::new(f);
 
-			let xz_decoder_ptr = StupidlyUnsafeProgressMonitorPtr(&mut xz_decoder as *mut _);
-			rayon::spawn(move || {
-				#[allow(clippy::redundant_locals)]
-				let xz_decoder_ptr = xz_decoder_ptr;
-				while !complete_ref.load(Ordering::Acquire) {
-					let xz_decoder = unsafe { &mut *xz_decoder_ptr.0 };
+		let bytes_total_f = bytes_total.max(1) as f64;
+		let mut decompressed_bytes: u64 = 0;
+		let mut last_progress = 0;
 
-					let bytes_read = xz_decoder.total_in() as f64;
-					transaction.progress(bytes_read / bytes_total_f);
+		let mut buf = vec![0u8; 64 * 1024];
+		let result = loop {
+			if transaction.aborted() {
+				return Err(GMAError::Cancelled);
+			}
 
-					let decompressed_bytes = xz_decoder.total_out();
-					if decompressed_bytes > bytes_total {
-						transaction.data((turbonone!(), decompressed_bytes));
-					}
+			let read = match xz_decoder.read(&mut buf) {
+				Ok(0) => break Ok(()),
+				Ok(read) => read,
+				Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(err) => break Err(err),
+			};
 
-					sleep_ms!(25);
-				}
-			});
+			w.write_all(&buf[..read])?;
+			decompressed_bytes += read as u64;
 
-			let result = xz_decoder.read_to_end(&mut output);
-			complete.store(true, Ordering::Release);
-			result
-		} else {
-			xz_decoder.read_to_end(&mut output)
+			// Only emit when the progress visibly changes, there can be a lot of chunks
+			let progress = xz_decoder.get_ref().read as f64 / bytes_total_f;
+			if (progress * 1000.) as u32 != last_progress {
+				last_progress = (progress * 1000.) as u32;
+				transaction.progress(progress);
+				if decompressed_bytes > bytes_total {
+					transaction.data((turbonone!(), decompressed_bytes));
+				}
+			}
 		};
 
 		if let Err(err) = result {
 			// No idea why, but XZ always errors with "corrupt xz stream" even when the decompression succeeds.
 			// Maybe a difference in the way Gmod encoded the XZ stream?
 			// Let's just check if the file has been fully read, then naively continue.
-			let mut input = xz_decoder.into_inner();
-			if input.read(&mut [0u8]).ok() != Some(0) {
+			if xz_decoder.get_mut().read(&mut [0u8]).ok() != Some(0) {
 				eprintln!("LZMA error: {err:#?}");
 				return Err(GMAError::LZMA);
 			}
 		}
 
-		output.shrink_to_fit();
-
-		let decompressed_size = output.len() as u64;
-
-		let mut gma = GMAFile::read_header(GMAReader::MemBuffer(Cursor::new(output.into())), path)?;
-		gma.size = decompressed_size;
+		w.flush()?;
 
-		Ok(gma)
+		Ok(())
 	}
 
 	fn stream_entry_bytes_with_transaction(
 		handle: &mut GMAReader,
-		entries_start: u64,
 		entry_path: &PathBuf,
 		entry: &GMAEntry,
 		transaction: &Transaction,
@@ -192,23 +313,78 @@ impl GMAFile {
 		fs::create_dir_all(entry_path.with_file_name(""))?;
 		let f = File::create(entry_path)?;
 
-		handle.seek(SeekFrom::Start(entries_start + entry.index))?;
+		handle
+			.seek(SeekFrom::Start(entry.offset))
+			.map_err(|error| GMAError::entry_data(error, entry))?;
 
 		let mut w = BufWriter::new(f);
-		crate::stream_bytes_with_transaction(&mut **handle, &mut w, entry.size as usize, transaction)?;
+		crate::stream_bytes_with_transaction(&mut **handle, &mut w, entry.size as usize, transaction)
+			.map_err(|error| GMAError::entry_data(error, entry))?;
 
 		w.flush()?;
 
 		Ok(())
 	}
 
-	fn stream_entry_bytes(handle: &mut GMAReader, entries_start: u64, entry_path: &PathBuf, entry: &GMAEntry) -> Result<(), GMAError> {
+	/// Moves the files in `dest_path` that don't have an entry in this GMA to the recycle bin, returning how many were moved.
+	/// Only the top level folders this GMA has entries in are pruned, anything else in `dest_path` is left alone.
+	fn prune(&self, dest_path: &Path) -> usize {
+		let entries = self.entries.as_ref().expect("Expected entries to be read by this point");
+
+		let folders = entries
+			.iter()
+			.filter_map(|entry| {
+				let path = sanitize::entry_fs_path(entry);
+				let mut components = path.components();
+				let folder = components.next()?;
+				components.next().map(|_| folder.as_os_str().to_owned())
+			})
+			.collect::<HashSet<_>>();
+
+		// Entries that aren't UTF-8 are extracted to their raw path, which doesn't match the decoded path
+		let raw_paths = entries
+			.iter()
+			.filter(|entry| entry.raw_path.is_some())
+			.map(sanitize::entry_fs_path)
+			.collect::<HashSet<_>>();
+
+		let mut deleted = 0;
+		for file in folders
+			.iter()
+			.flat_map(|folder| WalkDir::new(dest_path.join(folder)))
+			.filter_map(|entry| entry.ok())
+		{
+			if !file.file_type().is_file() {
+				continue;
+			}
+
+			let relative_path = match file.path().strip_prefix(dest_path) {
+				Ok(relative_path) => relative_path,
+				Err(_) => continue,
+			};
+			if raw_paths.contains(relative_path) {
+				continue;
+			}
+
+			if entries.contains_path(&relative_path.to_slash_lossy().to_lowercase()) {
+				continue;
+			}
+
+			if trash::delete(file.path()).is_ok() {
+				deleted += 1;
+			}
+		}
+
+		deleted
+	}
+
+	fn stream_entry_bytes(handle: &mut GMAReader, entry_path: &PathBuf, entry: &GMAEntry) -> Result<(), std::io::Error> {
 		use std::io::Write;
 
 		fs::create_dir_all(entry_path.with_file_name(""))?;
 		let f = File::create(entry_path)?;
 
-		handle.seek(SeekFrom::Start(entries_start + entry.index))?;
+		handle.seek(SeekFrom::Start(entry.offset))?;
 
 		let mut w = BufWriter::new(f);
 		crate::stream_bytes(&mut **handle, &mut w, entry.size as usize)?;
@@ -226,7 +402,15 @@ pub trait ExtractGMAImmut {
 		transaction: &Transaction,
 		open_after_extract: bool,
 		ignore_whitelist: bool,
-	) -> Result<PathBuf, GMAError>;
+	) -> Result<ExtractReport, GMAError>;
+	fn extract_filtered(
+		&self,
+		dest: ExtractDestination,
+		filter: &ExtractFilter,
+		transaction: &Transaction,
+		open_after_extract: bool,
+		ignore_whitelist: bool,
+	) -> Result<ExtractReport, GMAError>;
 	fn extract_entry(&self, entry_path: String, transaction: &Transaction, open_after_extract: bool) -> Result<PathBuf, GMAError>;
 	fn extract_entry_with_handle(
 		&self,
@@ -243,7 +427,15 @@ pub trait ExtractGMAMut {
 		transaction: &Transaction,
 		open_after_extract: bool,
 		ignore_whitelist: bool,
-	) -> Result<PathBuf, GMAError>;
+	) -> Result<ExtractReport, GMAError>;
+	fn extract_filtered(
+		&mut self,
+		dest: ExtractDestination,
+		filter: &ExtractFilter,
+		transaction: &Transaction,
+		open_after_extract: bool,
+		ignore_whitelist: bool,
+	) -> Result<ExtractReport, GMAError>;
 	fn extract_entry(&mut self, entry_path: String, transaction: &Transaction, open_after_extract: bool) -> Result<PathBuf, GMAError>;
 }
 impl ExtractGMAImmut for GMAFile {
@@ -253,76 +445,175 @@ impl ExtractGMAImmut for GMAFile {
 		transaction: &Transaction,
 		open_after_extract: bool,
 		ignore_whitelist: bool,
-	) -> Result<PathBuf, GMAError> {
+	) -> Result<ExtractReport, GMAError> {
+		ExtractGMAImmut::extract_filtered(self, dest, &ExtractFilter::default(), transaction, open_after_extract, ignore_whitelist)
+	}
+
+	fn extract_filtered(
+		&self,
+		dest: ExtractDestination,
+		filter: &ExtractFilter,
+		transaction: &Transaction,
+		open_after_extract: bool,
+		ignore_whitelist: bool,
+	) -> Result<ExtractReport, GMAError> {
 		let result = THREAD_POOL.install(move || {
+			let overwrite_mode = app_data!().settings.read().extract_overwrite_mode.clone();
+			let sync = matches!(overwrite_mode, ExtractionOverwriteMode::Sync | ExtractionOverwriteMode::SyncPrune);
+
+			// A directory that was picked to extract into directly can have anything in it, so it's never pruned
+			let prune = matches!(overwrite_mode, ExtractionOverwriteMode::SyncPrune) && dest.is_owned();
+
 			let dest_path = dest.prepare(&self.extracted_name);
-			let entries_start = self.pointers.entries;
 
-			let entries = self.entries.as_ref().unwrap();
+			// Duplicate paths would be extracted to the same file, only the last one is kept
+			// Filtering happens up front so that progress is measured against what's actually extracted
+			let mut skipped = Vec::new();
+			let entries: Vec<&GMAEntry> = self
+				.entries
+				.as_ref()
+				.unwrap()
+				.unique()
+				.filter(|entry| filter.matches(&entry.path))
+				.filter(|entry| {
+					if ignore_whitelist || whitelist::check(&entry.path) {
+						true
+					} else {
+						skipped.push(entry.path.clone());
+						false
+					}
+				})
+				.collect();
 			let entries_len_f = entries.len() as f64;
-			let entries_len_i = entries.len();
 
-			self.read()?; // Don't waste time with the threads if the file fails to open
+			// Don't waste time with the threads if the file fails to open
+			// If it's mapped or in memory, the threads can share it instead of opening their own handles
+			let shared = self.read()?;
 
 			let i = AtomicUsize::new(0);
 
-			let finished = |mut dest_path: PathBuf| {
-				if i.fetch_add(1, Ordering::AcqRel) > entries_len_i || transaction.aborted() {
-					return;
-				}
-
-				transaction.finished(dest_path.to_owned());
+			enum Outcome {
+				Extracted,
+				Unchanged,
+				Failed(ExtractFailure),
+				Rejected(RejectedEntry),
+			}
 
-				if open_after_extract {
-					crate::path::open(&dest_path);
-				}
+			let outcomes = entries
+				.par_iter()
+				.map(|entry| -> Result<Outcome, GMAError> {
+					if transaction.aborted() {
+						return Err(GMAError::Cancelled);
+					}
 
-				let metadata = self.metadata.as_ref().unwrap();
-				if let GMAMetadata::Standard { .. } = metadata {
-					if let Ok(json) = serde_json::ser::to_string_pretty(metadata) {
-						dest_path.push("addon.json");
-						if let Some(parent) = dest_path.parent() {
-							ignore! { fs::create_dir_all(parent) };
+					let entry_dest_path = match sanitize::safe_join_entry(&dest_path, entry) {
+						Ok(entry_dest_path) => entry_dest_path,
+						Err(reason) => {
+							i.fetch_add(1, Ordering::AcqRel);
+							return Ok(Outcome::Rejected(RejectedEntry {
+								path: entry.path.clone(),
+								reason,
+							}));
 						}
-						ignore! { fs::write(dest_path, json.as_bytes()) };
-					}
-				}
-			};
+					};
 
-			entries
-				.par_iter()
-				.try_for_each(|(entry_path, entry)| -> Result<(), GMAError> {
-					let mut handle = self.read()?;
+					let unchanged = sync
+						&& match crc32_file(&entry_dest_path) {
+							Ok((size, crc)) => size == entry.size && crc == entry.crc,
+							Err(_) => false,
+						};
 
-					if ignore_whitelist || whitelist::check(entry_path) {
-						if transaction.aborted() {
-							return Err(GMAError::Cancelled);
+					let result = if unchanged {
+						Ok(())
+					} else {
+						match shared.share() {
+							Some(mut handle) => GMAFile::stream_entry_bytes(&mut handle, &entry_dest_path, entry),
+							None => match self.read() {
+								Ok(mut handle) => GMAFile::stream_entry_bytes(&mut handle, &entry_dest_path, entry),
+								Err(_) => Err(std::io::ErrorKind::Other.into()),
+							},
 						}
+					};
+
+					let i = i.fetch_add(1, Ordering::AcqRel) + 1;
+					transaction.progress((i as f64) / entries_len_f);
+
+					Ok(match result {
+						Ok(_) if unchanged => Outcome::Unchanged,
+						Ok(_) => Outcome::Extracted,
+						Err(error) => Outcome::Failed(ExtractFailure {
+							path: entry.path.clone(),
+							error: format!("{:?}", error.kind()),
+						}),
+					})
+				})
+				.collect::<Result<Vec<_>, GMAError>>()?;
+
+			let mut extracted = Vec::new();
+			let mut unchanged = 0;
+			let mut failed = Vec::new();
+			let mut rejected = self.entries.as_ref().unwrap().rejected().to_vec();
+			for (entry, outcome) in entries.iter().zip(outcomes) {
+				match outcome {
+					Outcome::Extracted => extracted.push(entry.path.clone()),
+					Outcome::Unchanged => unchanged += 1,
+					Outcome::Failed(failure) => failed.push(failure),
+					Outcome::Rejected(entry) => rejected.push(entry),
+				}
+			}
 
-						// FIXME count errors, check if errors == number of entries, return an error instead of finished
-						ignore! { GMAFile::stream_entry_bytes(&mut handle, entries_start, &dest_path.join(entry_path), entry) };
+			// Entries that were filtered out, skipped or rejected aren't failures, so that alone still succeeds
+			if !failed.is_empty() && extracted.is_empty() && unchanged == 0 {
+				return Err(GMAError::ExtractionFailed(Box::new(ExtractReport {
+					path: dest_path,
+					extracted,
+					unchanged,
+					deleted: 0,
+					skipped,
+					failed,
+					rejected,
+				})));
+			}
 
-						let i = i.fetch_add(1, Ordering::AcqRel) + 1;
-						transaction.progress((i as f64) / entries_len_f);
+			let deleted = if prune { self.prune(&dest_path) } else { 0 };
 
-						if i == entries_len_i {
-							(finished)(dest_path.to_owned());
-						}
-					} else {
-						transaction.error("ERR_WHITELIST", entry_path.clone()); // TODO
-					}
+			let metadata = self.metadata.as_ref().unwrap();
+			if let GMAMetadata::Standard { .. } = metadata {
+				if let Ok(json) = serde_json::ser::to_string_pretty(metadata) {
+					ignore! { fs::create_dir_all(&dest_path) };
+					ignore! { fs::write(dest_path.join("addon.json"), json.as_bytes()) };
+				}
+			}
 
-					Ok(())
-				})
-				.map(|_| {
-					(finished)(dest_path.to_owned());
-					dest_path
-				})
+			Ok(ExtractReport {
+				path: dest_path,
+				extracted,
+				unchanged,
+				deleted,
+				skipped,
+				failed,
+				rejected,
+			})
 		});
 
-		if !transaction.aborted() {
-			if let Err(ref error) = result {
-				transaction.error(error.to_string(), turbonone!());
+		match result {
+			Ok(ref report) => {
+				if !transaction.aborted() {
+					transaction.finished(report.clone());
+					if open_after_extract {
+						crate::path::open(&report.path);
+					}
+				}
+			}
+			Err(GMAError::ExtractionFailed(ref report)) => {
+				if !transaction.aborted() {
+					transaction.error("ERR_EXTRACTION_FAILED", report.clone());
+				}
+			}
+			Err(ref error) => {
+				if !transaction.aborted() {
+					transaction.error(error.to_string(), turbonone!());
+				}
 			}
 		}
 
@@ -336,25 +627,24 @@ impl ExtractGMAImmut for GMAFile {
 		open_after_extract: bool,
 		handle: Option<GMAReader>,
 	) -> Result<PathBuf, GMAError> {
+		let entry = self
+			.entries
+			.as_ref()
+			.expect("Expected entries to be read by this point")
+			.get_path(&entry_path)
+			.ok_or(GMAError::EntryNotFound)?;
+
 		let mut path = app_data!().temp_dir().to_owned();
 		path.push("gmpublisher");
 		path.push(&self.extracted_name);
-		path.push(&entry_path);
+		let path = sanitize::safe_join_entry(&path, entry).map_err(|_| GMAError::UnsafePath)?;
 
 		let mut handle = match handle {
 			Some(handle) => handle,
 			None => self.read()?,
 		};
 
-		let entry = self
-			.entries
-			.as_ref()
-			.expect("Expected entries to be read by this point")
-			.get(&entry_path)
-			.ok_or(GMAError::EntryNotFound)?;
-
-		let result =
-			GMAFile::stream_entry_bytes_with_transaction(&mut handle, self.pointers.entries, &path, entry, transaction).map(|_| path.to_owned());
+		let result = GMAFile::stream_entry_bytes_with_transaction(&mut handle, &path, entry, transaction).map(|_| path.to_owned());
 
 		if let Err(ref error) = result {
 			if !transaction.aborted() {
@@ -383,10 +673,20 @@ impl ExtractGMAMut for GMAFile {
 		transaction: &Transaction,
 		open_after_extract: bool,
 		ignore_whitelist: bool,
-	) -> Result<PathBuf, GMAError> {
+	) -> Result<ExtractReport, GMAError> {
+		ExtractGMAMut::extract_filtered(self, dest, &ExtractFilter::default(), transaction, open_after_extract, ignore_whitelist)
+	}
+	fn extract_filtered(
+		&mut self,
+		dest: ExtractDestination,
+		filter: &ExtractFilter,
+		transaction: &Transaction,
+		open_after_extract: bool,
+		ignore_whitelist: bool,
+	) -> Result<ExtractReport, GMAError> {
 		THREAD_POOL.install(move || {
 			self.entries()?;
-			(*self).extract(dest, transaction, open_after_extract, ignore_whitelist)
+			(*self).extract_filtered(dest, filter, transaction, open_after_extract, ignore_whitelist)
 		})
 	}
 	fn extract_entry(&mut self, entry_path: String, transaction: &Transaction, open_after_extract: bool) -> Result<PathBuf, GMAError> {
@@ -398,7 +698,7 @@ impl ExtractGMAMut for GMAFile {
 }
 
 #[tauri::command]
-pub fn extract_gma(gma_path: PathBuf, dest: ExtractDestination) -> Option<u32> {
+pub fn extract_gma(gma_path: PathBuf, dest: ExtractDestination, filter: Option<ExtractFilter>) -> Option<u32> {
 	let mut gma = GMAFile::open(gma_path).ok()?;
 	gma.entries().ok()?;
 
@@ -406,8 +706,166 @@ pub fn extract_gma(gma_path: PathBuf, dest: ExtractDestination) -> Option<u32> {
 	let id = transaction.id;
 
 	rayon::spawn(move || {
-		ignore! { gma.extract(dest, &transaction, true, true) };
+		ignore! { gma.extract_filtered(dest, &filter.unwrap_or_default(), &transaction, true, true) };
 	});
 
 	Some(id)
 }
+
+#[test]
+fn test_extract_filter() {
+	let filter = ExtractFilter::new(["materials/**", "!*.vtf"]);
+	assert!(filter.matches("materials/foo/bar.vmt"));
+	assert!(!filter.matches("materials/foo/bar.vtf"));
+	assert!(!filter.matches("lua/autorun/foo.lua"));
+
+	let filter = ExtractFilter::new(["!lua/*"]);
+	assert!(filter.matches("materials/foo/bar.vtf"));
+	assert!(!filter.matches("lua/autorun/foo.lua"));
+
+	assert!(ExtractFilter::default().matches("lua/autorun/foo.lua"));
+}
+
+#[test]
+fn test_prune() {
+	let mut gma = GMAFile::open_bytes(
+		super::test_gma(&[
+			("lua/autorun/kept.lua", b"print('kept')"),
+			("materials/kept.vmt", b"\"VertexLitGeneric\" {}"),
+		]),
+		"prune.gma",
+	)
+	.unwrap();
+	gma.entries().unwrap();
+
+	let dest = super::test_dir("prune");
+	for path in [
+		"lua/autorun/kept.lua",
+		"lua/autorun/removed.lua",
+		"lua/weapons/removed.lua",
+		"materials/kept.vmt",
+		"materials/removed/removed.vtf",
+		"addon.json",
+		"notes.txt",
+		"sound/unrelated.wav",
+		"docs/readme.txt",
+	] {
+		let path = dest.join(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(path, "").unwrap();
+	}
+
+	let deleted = gma.prune(&dest);
+	let exists = |path: &str| dest.join(path).exists();
+	let survived = (
+		exists("lua/autorun/kept.lua") && exists("materials/kept.vmt"),
+		exists("addon.json") && exists("notes.txt") && exists("sound/unrelated.wav") && exists("docs/readme.txt"),
+		exists("lua/autorun/removed.lua") || exists("lua/weapons/removed.lua") || exists("materials/removed/removed.vtf"),
+	);
+
+	fs::remove_dir_all(&dest).ok();
+
+	assert_eq!(deleted, 3);
+	assert_eq!(survived, (true, true, false));
+
+	// Folders the user picked are never pruned
+	assert!(!ExtractDestination::Directory(dest.clone()).is_owned());
+	assert!(ExtractDestination::NamedDirectory(dest).is_owned());
+	assert!(ExtractDestination::Temp.is_owned());
+}
+
+#[test]
+fn test_decompress() {
+	let bytes = super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")]);
+
+	let dir = super::test_dir("decompress");
+	let bin = dir.join("decompress.bin");
+	GMAFile::open_bytes(bytes.clone(), "decompress.gma")
+		.unwrap()
+		.compress_to(&bin, super::DEFAULT_LZMA_PRESET, &transaction!())
+		.unwrap();
+
+	// The same .bin can be decompressed more than once at a time
+	let first = GMAFile::decompress(&bin, transaction!()).unwrap();
+	let second = GMAFile::decompress(&bin, transaction!()).unwrap();
+	let decompressed = (fs::read(&first.path).ok(), fs::read(&second.path).ok());
+	fs::remove_file(&first.path).ok();
+	fs::remove_file(&second.path).ok();
+
+	// Neither a broken stream nor something that isn't a GMA leaves anything behind
+	let garbage = dir.join("garbage.bin");
+	fs::write(&garbage, b"not lzma").unwrap();
+	let garbage_result = GMAFile::decompress_to(&garbage, dir.join("garbage.gma"), &transaction!());
+	let not_gma = dir.join("not_gma.bin");
+	let mut xz_encoder = xz2::write::XzEncoder::new_stream(
+		Vec::new(),
+		xz2::stream::Stream::new_lzma_encoder(&xz2::stream::LzmaOptions::new_preset(super::DEFAULT_LZMA_PRESET).unwrap()).unwrap(),
+	);
+	std::io::Write::write_all(&mut xz_encoder, b"not a GMA").unwrap();
+	fs::write(&not_gma, xz_encoder.finish().unwrap()).unwrap();
+	let not_gma_result = GMAFile::decompress_to(&not_gma, dir.join("not_gma.gma"), &transaction!());
+	let leftovers = (dir.join("garbage.gma").exists(), dir.join("not_gma.gma").exists());
+
+	fs::remove_dir_all(&dir).ok();
+
+	assert_ne!(first.path, second.path);
+	assert!(first.path.starts_with(&*app_data!().temp_dir()));
+	assert_eq!(decompressed, (Some(bytes.clone()), Some(bytes)));
+
+	assert!(garbage_result.is_err());
+	assert!(matches!(not_gma_result, Err(GMAError::InvalidHeader)));
+	assert_eq!(leftovers, (false, false));
+}
+
+#[test]
+fn test_extract_report() {
+	let dir = super::test_dir("extract_report");
+	let extract = |gma: &[u8], dest: &str, filter: ExtractFilter| {
+		let mut gma = GMAFile::open_bytes(gma.to_vec(), "report.gma").unwrap();
+		ExtractGMAMut::extract_filtered(
+			&mut gma,
+			ExtractDestination::Directory(dir.join(dest)),
+			&filter,
+			&transaction!(),
+			false,
+			true,
+		)
+	};
+
+	let gma = super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("materials/b.vmt", b"\"VertexLitGeneric\" {}")]);
+
+	// Filtering everything out isn't a failure
+	let filtered = extract(&gma, "filtered", ExtractFilter::new(["sound/**"]));
+
+	// Neither is every entry being rejected
+	let rejected = extract(
+		&super::test_gma_unchecked(&[("lua/../../evil.lua", b"print('evil')")]),
+		"rejected",
+		ExtractFilter::default(),
+	);
+
+	// A file where a directory should be means nothing can be written, and the report says which entries failed
+	fs::create_dir_all(dir.join("blocked")).unwrap();
+	fs::write(dir.join("blocked/lua"), "").unwrap();
+	let failed = extract(&gma, "blocked", ExtractFilter::new(["lua/**"]));
+
+	fs::remove_dir_all(&dir).ok();
+
+	let filtered = filtered.unwrap();
+	assert!(filtered.extracted.is_empty() && filtered.failed.is_empty());
+
+	let rejected = rejected.unwrap();
+	assert!(rejected.extracted.is_empty());
+	assert_eq!(rejected.rejected.len(), 1);
+
+	match failed {
+		Err(GMAError::ExtractionFailed(report)) => {
+			assert!(report.extracted.is_empty());
+			assert_eq!(
+				report.failed.iter().map(|failure| failure.path.as_str()).collect::<Vec<_>>(),
+				["lua/autorun/a.lua"]
+			);
+		}
+		other => panic!("expected ExtractionFailed, got {:?}", other),
+	}
+}
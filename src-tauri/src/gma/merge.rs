@@ -0,0 +1,264 @@
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::transactions::Transaction;
+
+use super::{whitelist, GMABuilder, GMAEntry, GMAError, GMAFile};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GMAMergePolicy {
+	/// Keep the entry from the GMA that comes first
+	FirstWins,
+	/// Keep the entry from the GMA that comes last
+	LastWins,
+	/// Don't merge anything if there are any conflicts
+	Fail,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GMAMergeSource {
+	pub gma: PathBuf,
+	pub size: u64,
+	pub crc: u32,
+}
+
+/// A path that more than one of the merged GMAs have different contents for
+#[derive(Debug, Clone, Serialize)]
+pub struct GMAMergeConflict {
+	pub path: String,
+	/// Every GMA that has an entry at this path, in merge order
+	pub sources: Vec<GMAMergeSource>,
+	/// Index into `sources` of the entry that was kept, if any
+	pub kept: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GMAMergeReport {
+	pub path: PathBuf,
+	pub entries: usize,
+	pub conflicts: Vec<GMAMergeConflict>,
+}
+
+impl GMAFile {
+	/// Merges `gmas` into a new GMA at `dest`, which takes its metadata and header from the first GMA.
+	///
+	/// Entries with the same path and contents in several GMAs aren't conflicts, and are only written once.
+	/// With `GMAMergePolicy::Fail`, any conflicts are returned as `GMAError::MergeConflict` and nothing is written.
+	/// Entries that don't pass the whitelist fail the merge with `GMAError::NotWhitelistedEntries`.
+	pub fn merge<P: AsRef<Path>>(
+		gmas: &mut [GMAFile],
+		dest: P,
+		policy: GMAMergePolicy,
+		transaction: &Transaction,
+	) -> Result<GMAMergeReport, GMAError> {
+		main_thread_forbidden!();
+
+		for gma in gmas.iter_mut() {
+			gma.entries()?;
+			gma.entries.as_ref().unwrap().ensure_none_rejected()?;
+		}
+		let gmas = &*gmas;
+
+		let first = gmas.first().ok_or(GMAError::EntryNotFound)?;
+
+		let mut paths: BTreeMap<&str, Vec<(&GMAFile, &GMAEntry)>> = BTreeMap::new();
+		for gma in gmas {
+			for entry in gma.entries.as_ref().unwrap().unique() {
+				paths.entry(entry.path.as_str()).or_default().push((gma, entry));
+			}
+		}
+
+		let not_whitelisted = paths
+			.keys()
+			.filter(|path| !whitelist::check(path))
+			.map(|path| path.to_string())
+			.collect::<Vec<_>>();
+		if !not_whitelisted.is_empty() {
+			return Err(GMAError::NotWhitelistedEntries(not_whitelisted));
+		}
+
+		let mut conflicts = Vec::new();
+		let mut builder = GMABuilder::new(first.metadata.clone().unwrap());
+		builder.header(first.header.clone().unwrap_or_default());
+
+		for (path, sources) in paths {
+			let (_, first_entry) = sources[0];
+			let conflict = sources
+				.iter()
+				.any(|(_, entry)| entry.size != first_entry.size || entry.crc != first_entry.crc);
+
+			let kept = match policy {
+				_ if !conflict => Some(0),
+				GMAMergePolicy::FirstWins => Some(0),
+				GMAMergePolicy::LastWins => Some(sources.len() - 1),
+				GMAMergePolicy::Fail => None,
+			};
+
+			if conflict {
+				conflicts.push(GMAMergeConflict {
+					path: path.to_owned(),
+					sources: sources
+						.iter()
+						.map(|(gma, entry)| GMAMergeSource {
+							gma: gma.path.clone(),
+							size: entry.size,
+							crc: entry.crc,
+						})
+						.collect(),
+					kept,
+				});
+			}
+
+			if let Some(kept) = kept {
+				let (gma, entry) = sources[kept];
+				builder.add_entry(gma, entry)?;
+			}
+		}
+
+		if policy == GMAMergePolicy::Fail && !conflicts.is_empty() {
+			return Err(GMAError::MergeConflict(conflicts));
+		}
+
+		let entries = builder.len();
+		let dest = dest.as_ref();
+		builder.write_to_path(dest, transaction)?;
+
+		Ok(GMAMergeReport {
+			path: dest.to_owned(),
+			entries,
+			conflicts,
+		})
+	}
+}
+
+#[tauri::command]
+pub fn merge_gmas(gma_paths: Vec<PathBuf>, dest: PathBuf, policy: GMAMergePolicy) -> Option<u32> {
+	let transaction = transaction!();
+	let id = transaction.id;
+
+	rayon::spawn(move || {
+		let result = gma_paths
+			.into_iter()
+			.map(GMAFile::open)
+			.collect::<Result<Vec<_>, _>>()
+			.and_then(|mut gmas| GMAFile::merge(&mut gmas, &dest, policy, &transaction));
+
+		match result {
+			Ok(report) => transaction.finished(report),
+			Err(GMAError::MergeConflict(conflicts)) => transaction.error("ERR_GMA_MERGE_CONFLICT", conflicts),
+			Err(GMAError::NotWhitelistedEntries(paths)) => transaction.error("ERR_WHITELIST", paths.join("\n")),
+			Err(error) => {
+				if !transaction.aborted() {
+					transaction.error(error.to_string(), turbonone!());
+				}
+			}
+		}
+	});
+
+	Some(id)
+}
+
+#[test]
+fn test_merge() {
+	use std::io::Read;
+
+	let dir = super::test_dir("merge");
+
+	let gmas = || {
+		vec![
+			GMAFile::open_bytes(
+				super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/shared.lua", b"print('first')")]),
+				"first.gma",
+			)
+			.unwrap(),
+			GMAFile::open_bytes(
+				super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")]),
+				"second.gma",
+			)
+			.unwrap(),
+			GMAFile::open_bytes(super::test_gma(&[("lua/autorun/shared.lua", b"print('last')")]), "third.gma").unwrap(),
+		]
+	};
+
+	let merged = |policy: GMAMergePolicy| {
+		let dest = dir.join(format!("{:?}.gma", policy));
+		let report = GMAFile::merge(&mut gmas(), &dest, policy, &transaction!());
+		let contents = report.as_ref().ok().map(|_| {
+			let mut gma = GMAFile::open(&dest).unwrap();
+			gma.entries().unwrap();
+			assert!(gma.verify(&transaction!()).unwrap().ok);
+			gma.entries
+				.as_ref()
+				.unwrap()
+				.iter()
+				.map(|entry| {
+					let mut contents = String::new();
+					gma.open_entry(&entry.path).unwrap().read_to_string(&mut contents).unwrap();
+					(entry.path.clone(), contents)
+				})
+				.collect::<Vec<_>>()
+		});
+		(report, contents, dest)
+	};
+
+	let (first_wins, first_wins_contents, _) = merged(GMAMergePolicy::FirstWins);
+	let (last_wins, last_wins_contents, _) = merged(GMAMergePolicy::LastWins);
+	let (fail, _, fail_dest) = merged(GMAMergePolicy::Fail);
+
+	let not_whitelisted_dest = dir.join("not_whitelisted.gma");
+	let not_whitelisted = GMAFile::merge(
+		&mut [
+			gmas().remove(0),
+			GMAFile::open_bytes(super::test_gma_unchecked(&[("virus.exe", b"MZ")]), "virus.gma").unwrap(),
+		],
+		&not_whitelisted_dest,
+		GMAMergePolicy::FirstWins,
+		&transaction!(),
+	);
+	let not_whitelisted_written = not_whitelisted_dest.exists();
+
+	std::fs::remove_dir_all(&dir).ok();
+
+	// Entries that are the same in every GMA aren't conflicts
+	let first_wins = first_wins.unwrap();
+	assert_eq!(first_wins.entries, 3);
+	assert_eq!(first_wins.conflicts.len(), 1);
+	let conflict = &first_wins.conflicts[0];
+	assert_eq!(conflict.path, "lua/autorun/shared.lua");
+	assert_eq!(
+		conflict.sources.iter().map(|source| (source.gma.clone(), source.crc)).collect::<Vec<_>>(),
+		[
+			(PathBuf::from("first.gma"), crc32fast::hash(b"print('first')")),
+			(PathBuf::from("third.gma"), crc32fast::hash(b"print('last')"))
+		]
+	);
+	assert_eq!(conflict.kept, Some(0));
+
+	let expected = |shared: &str| {
+		vec![
+			("lua/autorun/a.lua".to_string(), "print('a')".to_string()),
+			("lua/autorun/b.lua".to_string(), "print('b')".to_string()),
+			("lua/autorun/shared.lua".to_string(), shared.to_string()),
+		]
+	};
+	assert_eq!(first_wins_contents.unwrap(), expected("print('first')"));
+
+	assert_eq!(last_wins.unwrap().conflicts[0].kept, Some(1));
+	assert_eq!(last_wins_contents.unwrap(), expected("print('last')"));
+
+	match fail {
+		Err(GMAError::MergeConflict(conflicts)) => {
+			assert_eq!(conflicts.len(), 1);
+			assert_eq!(conflicts[0].kept, None);
+		}
+		_ => panic!("Expected the merge to fail on the conflict"),
+	}
+	assert!(!fail_dest.exists());
+
+	assert!(matches!(not_whitelisted, Err(GMAError::NotWhitelistedEntries(ref paths)) if paths == &["virus.exe"]));
+	assert!(!not_whitelisted_written);
+}
@@ -2,7 +2,7 @@ use std::{
 	collections::HashMap,
 	fmt::Display,
 	fs::File,
-	io::{BufReader, SeekFrom},
+	io::{BufReader, Cursor, SeekFrom},
 	path::{Path, PathBuf},
 	time::SystemTime,
 };
@@ -17,31 +17,225 @@ use crate::{game_addons::GameAddons, main_thread_forbidden, ArcBytes};
 
 const GMA_HEADER: &[u8; 4] = b"GMAD";
 
+/// The part of a GMA that was being parsed when something went wrong
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum GMAStructure {
+	Header,
+	Metadata,
+	Index,
+	EntryData,
+}
+
+/// A limit from `GMAParseLimits` that a GMA went over
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum GMALimit {
+	EntryCount,
+	PathLength,
+	StringLength,
+	RequiredContentCount,
+	/// The entries add up to more data than there is in the file
+	EntriesSize,
+}
+
+fn serialize_io_error_kind<S: serde::Serializer>(kind: &std::io::ErrorKind, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.collect_str(&format_args!("{:?}", kind))
+}
+
 #[derive(Debug, Clone, Serialize, Error)]
 pub enum GMAError {
-	IOError,
-	FormatError,
+	IOError {
+		#[serde(serialize_with = "serialize_io_error_kind")]
+		kind: std::io::ErrorKind,
+		/// Byte offset in the GMA, if the error happened while reading it
+		offset: Option<u64>,
+		/// Path of the entry being read or written, if any
+		entry: Option<String>,
+	},
+	FormatError {
+		structure: GMAStructure,
+		/// Byte offset in the GMA where parsing failed
+		offset: u64,
+		entry: Option<String>,
+	},
+	LimitExceeded {
+		limit: GMALimit,
+		/// Byte offset in the GMA of whatever went over the limit
+		offset: u64,
+		entry: Option<String>,
+	},
 	InvalidHeader,
 	EntryNotFound,
+	EntryExists {
+		entry: Option<String>,
+	},
+	NotWhitelisted,
+	/// Entries of the GMA being rewritten that don't pass the whitelist
+	NotWhitelistedEntries(Vec<String>),
+	/// None of the entries could be written, the report says why
+	ExtractionFailed(Box<ExtractReport>),
+	UnsafePath,
+	/// The GMA has entries that were rejected when it was read, which rewriting it would lose
+	RejectedEntries(Vec<RejectedEntry>),
+	InvalidArchive,
+	AddonJsonMissing,
+	MergeConflict(Vec<GMAMergeConflict>),
+	/// Files that must stay together in one GMA don't fit in the size a split GMA is allowed
+	SplitGroupTooLarge {
+		group: String,
+		size: u64,
+	},
 	LZMA,
 	Cancelled,
 }
-impl Display for GMAError {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl GMAError {
+	pub(crate) fn format(structure: GMAStructure, offset: u64) -> Self {
+		Self::FormatError {
+			structure,
+			offset,
+			entry: None,
+		}
+	}
+
+	pub(crate) fn limit(limit: GMALimit, offset: u64) -> Self {
+		Self::LimitExceeded { limit, offset, entry: None }
+	}
+
+	pub(crate) fn entry_exists<S: Into<String>>(path: S) -> Self {
+		Self::EntryExists { entry: Some(path.into()) }
+	}
+
+	/// Attaches the path of the entry that was being read or written
+	pub(crate) fn with_entry<S: Into<String>>(mut self, path: S) -> Self {
+		match &mut self {
+			Self::IOError { entry, .. } | Self::FormatError { entry, .. } | Self::LimitExceeded { entry, .. } | Self::EntryExists { entry } => {
+				*entry = Some(path.into())
+			}
+			_ => {}
+		}
+		self
+	}
+
+	/// Attaches the byte offset in the GMA an I/O error happened at
+	pub(crate) fn at(mut self, at: u64) -> Self {
+		if let Self::IOError { offset, .. } = &mut self {
+			*offset = Some(at);
+		}
+		self
+	}
+
+	/// Converts an error that happened while reading the contents of `entry`. Running out of bytes means the GMA is truncated.
+	pub(crate) fn entry_data(error: std::io::Error, entry: &GMAEntry) -> Self {
+		match error.kind() {
+			std::io::ErrorKind::UnexpectedEof => Self::format(GMAStructure::EntryData, entry.offset),
+			_ => GMAError::from(error).at(entry.offset),
+		}
+		.with_entry(entry.path.as_str())
+	}
+
+	/// The ERR_* code shown by the frontend
+	pub fn code(&self) -> &'static str {
 		use GMAError::*;
 		match self {
-			IOError => write!(f, "ERR_IO_ERROR"),
-			FormatError => write!(f, "ERR_GMA_FORMAT_ERROR"),
-			InvalidHeader => write!(f, "ERR_GMA_INVALID_HEADER"),
-			EntryNotFound => write!(f, "ERR_GMA_ENTRY_NOT_FOUND"),
-			LZMA => write!(f, "ERR_LZMA"),
-			Cancelled => write!(f, "ERR_CANCELLED"),
+			IOError { .. } => "ERR_IO_ERROR",
+			FormatError { .. } => "ERR_GMA_FORMAT_ERROR",
+			LimitExceeded { limit, .. } => match limit {
+				GMALimit::EntryCount => "ERR_GMA_TOO_MANY_ENTRIES",
+				GMALimit::PathLength => "ERR_GMA_PATH_TOO_LONG",
+				GMALimit::StringLength => "ERR_GMA_STRING_TOO_LONG",
+				GMALimit::RequiredContentCount => "ERR_GMA_TOO_MANY_REQUIRED_CONTENT",
+				GMALimit::EntriesSize => "ERR_GMA_ENTRIES_EXCEED_FILE",
+			},
+			InvalidHeader => "ERR_GMA_INVALID_HEADER",
+			EntryNotFound => "ERR_GMA_ENTRY_NOT_FOUND",
+			EntryExists { .. } => "ERR_GMA_ENTRY_EXISTS",
+			NotWhitelisted | NotWhitelistedEntries(_) => "ERR_WHITELIST",
+			ExtractionFailed(_) => "ERR_EXTRACTION_FAILED",
+			UnsafePath => "ERR_GMA_UNSAFE_PATH",
+			RejectedEntries(_) => "ERR_GMA_REJECTED_ENTRIES",
+			InvalidArchive => "ERR_INVALID_ARCHIVE",
+			AddonJsonMissing => "ERR_ADDON_JSON_MISSING",
+			MergeConflict(_) => "ERR_GMA_MERGE_CONFLICT",
+			SplitGroupTooLarge { .. } => "ERR_GMA_SPLIT_GROUP_TOO_LARGE",
+			LZMA => "ERR_LZMA",
+			Cancelled => "ERR_CANCELLED",
+		}
+	}
+}
+impl Display for GMAError {
+	/// Writes the ERR_* code. The alternate form (`{:#}`) also writes whatever context the error carries.
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.code())?;
+
+		if f.alternate() {
+			match self {
+				GMAError::IOError { kind, offset, entry } => {
+					write!(f, " ({:?}", kind)?;
+					if let Some(offset) = offset {
+						write!(f, " at byte {}", offset)?;
+					}
+					if let Some(entry) = entry {
+						write!(f, ", entry {}", entry)?;
+					}
+					f.write_str(")")?;
+				}
+				GMAError::FormatError { structure, offset, entry } => {
+					write!(f, " ({:?} at byte {}", structure, offset)?;
+					if let Some(entry) = entry {
+						write!(f, ", entry {}", entry)?;
+					}
+					f.write_str(")")?;
+				}
+				GMAError::EntryExists { entry: Some(entry) } => write!(f, " ({})", entry)?,
+				GMAError::RejectedEntries(rejected) => {
+					write!(f, " ({} unsafe paths", rejected.len())?;
+					for entry in rejected.iter() {
+						write!(f, ", {} ({:?})", entry.path, entry.reason)?;
+					}
+					f.write_str(")")?;
+				}
+				GMAError::NotWhitelistedEntries(paths) => {
+					write!(f, " ({} not whitelisted", paths.len())?;
+					for path in paths.iter() {
+						write!(f, ", {}", path)?;
+					}
+					f.write_str(")")?;
+				}
+				GMAError::SplitGroupTooLarge { group, size } => write!(f, " ({} is {} bytes)", group, size)?,
+				GMAError::ExtractionFailed(report) => {
+					write!(f, " ({} failed", report.failed.len())?;
+					for failure in report.failed.iter() {
+						write!(f, ", {} ({})", failure.path, failure.error)?;
+					}
+					f.write_str(")")?;
+				}
+				GMAError::MergeConflict(conflicts) => {
+					write!(f, " ({} conflicting paths", conflicts.len())?;
+					for conflict in conflicts.iter() {
+						write!(f, ", {}", conflict.path)?;
+					}
+					f.write_str(")")?;
+				}
+				GMAError::LimitExceeded { limit, offset, entry } => {
+					write!(f, " ({:?} at byte {}", limit, offset)?;
+					if let Some(entry) = entry {
+						write!(f, ", entry {}", entry)?;
+					}
+					f.write_str(")")?;
+				}
+				_ => {}
+			}
 		}
+
+		Ok(())
 	}
 }
 impl From<std::io::Error> for GMAError {
-	fn from(_: std::io::Error) -> Self {
-		Self::IOError
+	fn from(error: std::io::Error) -> Self {
+		Self::IOError {
+			kind: error.kind(),
+			offset: None,
+			entry: None,
+		}
 	}
 }
 
@@ -100,6 +294,12 @@ impl GMAMetadata {
 			_ => None,
 		}
 	}
+
+	/// Reads the addon.json in `dir`, if there is one
+	pub fn read_addon_json<P: AsRef<Path>>(dir: P) -> Option<GMAMetadata> {
+		let json = std::fs::read_to_string(dir.as_ref().join("addon.json")).ok()?;
+		serde_json::de::from_str(&json).ok()
+	}
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -108,8 +308,120 @@ pub struct GMAEntry {
 	pub size: u64,
 	pub crc: u32,
 
+	/// Absolute offset of the entry's contents in the GMA
+	pub offset: u64,
+
+	/// Another entry in the GMA has the same path
+	pub duplicate: bool,
+
+	/// The path as it's stored in the GMA, if it isn't UTF-8 and `path` had to be decoded from another encoding
 	#[serde(skip)]
-	pub index: u64,
+	pub raw_path: Option<Vec<u8>>,
+}
+impl GMAEntry {
+	/// The path as it's stored in the GMA, which is what has to be written back for the game to find the file
+	pub fn path_bytes(&self) -> &[u8] {
+		match &self.raw_path {
+			Some(raw_path) => raw_path,
+			None => self.path.as_bytes(),
+		}
+	}
+}
+
+/// The entries of a GMA, in the order they appear in the file
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct GMAEntries {
+	entries: Vec<GMAEntry>,
+
+	/// Points at the last entry with each path, which is the one that ends up on disk when extracting
+	#[serde(skip)]
+	paths: HashMap<String, usize>,
+
+	/// Entries that were left out of the index because their paths are unsafe to extract
+	#[serde(skip)]
+	rejected: Vec<RejectedEntry>,
+}
+impl GMAEntries {
+	pub fn push(&mut self, mut entry: GMAEntry) {
+		if let Some(prev) = self.paths.insert(entry.path.clone(), self.entries.len()) {
+			self.entries[prev].duplicate = true;
+			entry.duplicate = true;
+		}
+		self.entries.push(entry);
+	}
+
+	pub fn get_path(&self, path: &str) -> Option<&GMAEntry> {
+		self.paths.get(path).map(|i| &self.entries[*i])
+	}
+
+	pub fn contains_path(&self, path: &str) -> bool {
+		self.paths.contains_key(path)
+	}
+
+	/// Entries that aren't shadowed by a later entry with the same path
+	pub fn unique(&self) -> impl Iterator<Item = &GMAEntry> {
+		self.entries
+			.iter()
+			.enumerate()
+			.filter(|(i, entry)| self.paths.get(&entry.path) == Some(i))
+			.map(|(_, entry)| entry)
+	}
+
+	pub fn duplicates(&self) -> impl Iterator<Item = &GMAEntry> {
+		self.entries.iter().filter(|entry| entry.duplicate)
+	}
+
+	pub fn rejected(&self) -> &[RejectedEntry] {
+		&self.rejected
+	}
+
+	/// Fails if any entries were rejected when reading the GMA, since a GMA rewritten from these entries would silently lose them
+	pub fn ensure_none_rejected(&self) -> Result<(), GMAError> {
+		if self.rejected.is_empty() {
+			Ok(())
+		} else {
+			Err(GMAError::RejectedEntries(self.rejected.clone()))
+		}
+	}
+}
+impl std::ops::Deref for GMAEntries {
+	type Target = [GMAEntry];
+
+	fn deref(&self) -> &Self::Target {
+		&self.entries
+	}
+}
+impl<'a> IntoIterator for &'a GMAEntries {
+	type Item = &'a GMAEntry;
+	type IntoIter = std::slice::Iter<'a, GMAEntry>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.entries.iter()
+	}
+}
+
+/// The fields of the GMA header that aren't part of the addon.json metadata
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GMAHeader {
+	#[serde(serialize_with = "serde_u64_str")]
+	pub steamid: u64,
+	pub timestamp: u64,
+	pub required_content: Vec<String>,
+	pub author: String,
+	pub addon_version: i32,
+}
+impl Default for GMAHeader {
+	fn default() -> Self {
+		Self {
+			steamid: 0,
+			timestamp: 0,
+			required_content: Vec::new(),
+			author: "Author Name".to_string(),
+			addon_version: 1,
+		}
+	}
 }
 
 #[derive(Clone, Serialize)]
@@ -124,7 +436,9 @@ pub struct GMAFile {
 	#[serde(flatten)]
 	pub metadata: Option<GMAMetadata>,
 
-	pub entries: Option<HashMap<String, GMAEntry>>,
+	pub header: Option<GMAHeader>,
+
+	pub entries: Option<GMAEntries>,
 
 	#[serde(skip)]
 	pub pointers: GMAFilePointers,
@@ -147,6 +461,7 @@ impl std::fmt::Debug for GMAFile {
 			.field("size", &self.size)
 			.field("id", &self.id)
 			.field("metadata", &self.metadata)
+			.field("header", &self.header)
 			.field("entries", &self.entries)
 			.field("pointers", &self.pointers)
 			.field("version", &self.version)
@@ -173,18 +488,29 @@ impl Ord for GMAFile {
 }
 
 impl GMAFile {
-	fn read_header<P: AsRef<Path>>(mut f: GMAReader, path: P) -> Result<GMAFile, GMAError> {
-		let mut gma = GMAFile {
-			size: path.as_ref().metadata().map(|metadata| metadata.len()).unwrap_or(0),
-			path: path.as_ref().to_owned(),
+	/// A GMA that doesn't exist yet, to be written to `path` by `create`, `create_from_zip` or `create_split`
+	pub fn new<P: Into<PathBuf>>(path: P, metadata: Option<GMAMetadata>) -> GMAFile {
+		GMAFile {
+			path: path.into(),
+			size: 0,
 			id: None,
-			metadata: None,
+			metadata,
+			header: None,
 			entries: None,
 			pointers: GMAFilePointers::default(),
-			version: 0,
+			version: 3,
 			extracted_name: String::new(),
 			modified: None,
 			membuffer: None,
+		}
+	}
+
+	fn read_header<P: AsRef<Path>>(mut f: GMAReader, path: P) -> Result<GMAFile, GMAError> {
+		let mut gma = GMAFile::new(path.as_ref(), None);
+		gma.size = match &f {
+			// The path of a GMA that's in memory only names it, there might not be a file there
+			GMAReader::MemBuffer(buf) => buf.get_ref().len() as u64,
+			_ => path.as_ref().metadata().map(|metadata| metadata.len()).unwrap_or(0),
 		};
 
 		if gma.size == 0 {
@@ -199,7 +525,7 @@ impl GMAFile {
 			return Err(GMAError::InvalidHeader);
 		}
 
-		gma.version = f.read_u8()?;
+		gma.version = f.read_u8().map_err(|_| GMAError::format(GMAStructure::Header, GMA_HEADER.len() as u64))?;
 
 		gma.pointers.metadata = f.seek(SeekFrom::Current(0))?;
 
@@ -217,6 +543,11 @@ impl GMAFile {
 		GMAFile::read_header(GMAReader::Disk(BufReader::new(File::open(path.as_ref())?)), path)
 	}
 
+	/// Opens a GMA that's already in memory, such as one piped in through stdin. `path` is only used to name it.
+	pub fn open_bytes<P: AsRef<Path>>(bytes: Vec<u8>, path: P) -> Result<GMAFile, GMAError> {
+		GMAFile::read_header(GMAReader::MemBuffer(Cursor::new(ArcBytes::from(bytes))), path)
+	}
+
 	pub fn set_ws_id(&mut self, id: PublishedFileId) {
 		let compute = self.id.is_some() || self.metadata.is_some();
 
@@ -292,6 +623,14 @@ impl GMAFile {
 	}
 }
 
+// SteamIDs don't fit in a JavaScript number
+fn serde_u64_str<S>(n: &u64, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: serde::Serializer,
+{
+	serializer.serialize_str(&n.to_string())
+}
+
 fn serde_canonicalize<S>(path: &PathBuf, serializer: S) -> Result<S::Ok, S::Error>
 where
 	S: serde::Serializer,
@@ -315,3 +654,88 @@ pub mod write;
 pub use write::*;
 
 pub mod preview;
+
+pub mod verify;
+pub use verify::*;
+
+pub mod diff;
+pub use diff::*;
+
+pub mod edit;
+pub use edit::*;
+
+pub mod compress;
+pub use compress::*;
+
+pub mod builder;
+pub use builder::*;
+
+pub mod split;
+pub use split::*;
+
+pub mod merge;
+pub use merge::*;
+
+pub mod archive;
+pub use archive::*;
+
+pub mod sanitize;
+pub use sanitize::{RejectedEntry, UnsafePathReason};
+
+#[cfg(test)]
+pub(crate) fn test_metadata() -> GMAMetadata {
+	GMAMetadata::Legacy {
+		title: "Test".to_string(),
+		description: String::new(),
+	}
+}
+
+/// Packs `entries` into a GMA in memory
+#[cfg(test)]
+pub(crate) fn test_gma(entries: &[(&str, &[u8])]) -> Vec<u8> {
+	let mut builder = GMABuilder::new(test_metadata());
+	for (path, contents) in entries {
+		builder.add_bytes(path, contents.to_vec()).unwrap();
+	}
+	builder.write_to(Cursor::new(Vec::new()), &transaction!()).unwrap().into_inner()
+}
+
+/// Writes `entries` into a GMA in memory as-is, without any of the checks `GMABuilder` makes, so they can be duplicated or unsafe
+#[cfg(test)]
+pub(crate) fn test_gma_unchecked(entries: &[(&str, &[u8])]) -> Vec<u8> {
+	let header = GMACreateOptions::default().header(&test_metadata(), &GMAHeader::default()).unwrap();
+	let mut w = GMAWriter::new(
+		Cursor::new(Vec::new()),
+		header,
+		entries.iter().map(|(path, data)| (*path, data.len() as u64)),
+	)
+	.unwrap();
+	for (_, data) in entries {
+		w.write_entry(&mut &data[..]).unwrap();
+	}
+	w.finish().unwrap().into_inner()
+}
+
+/// An empty directory for the test `name` to work in, which the test removes once it's done
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("gmpublisher_test_{}_{}", name, std::process::id()));
+	std::fs::remove_dir_all(&path).ok();
+	std::fs::create_dir_all(&path).unwrap();
+	path
+}
+
+#[test]
+fn test_open_bytes_size() {
+	let bytes = test_gma(&[("lua/autorun/test.lua", b"print('test')")]);
+	let len = bytes.len() as u64;
+
+	// A file at the path it's named after shouldn't be mistaken for it
+	let dir = test_dir("open_bytes_size");
+	let path = dir.join("test.gma");
+	std::fs::write(&path, b"not this").unwrap();
+	let gma = GMAFile::open_bytes(bytes, &path).unwrap();
+	std::fs::remove_dir_all(&dir).ok();
+
+	assert_eq!(gma.size, len);
+}
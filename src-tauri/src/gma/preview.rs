@@ -1,14 +1,32 @@
 use std::{path::PathBuf, sync::Arc};
 
-use super::{extract::ExtractGMAImmut, ExtractDestination, GMAEntry, GMAError, GMAFile};
+use super::{extract::ExtractGMAImmut, ExtractDestination, ExtractFilter, GMAEditSource, GMAEditor, GMAEntry, GMAError, GMAFile, GMAHeader};
 use parking_lot::Mutex;
+use serde::Serialize;
 
 lazy_static! {
 	static ref PREVIEW_GMA: Mutex<Option<Arc<GMAFile>>> = Mutex::new(None);
 }
 
+#[derive(Serialize)]
+pub struct GMAPreview {
+	entries: Vec<GMAEntry>,
+	header: Option<GMAHeader>,
+}
+impl GMAPreview {
+	fn new(gma: &GMAFile) -> Self {
+		let mut entries: Vec<GMAEntry> = gma.entries.as_ref().unwrap().to_vec();
+		entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+		GMAPreview {
+			entries,
+			header: gma.header.clone(),
+		}
+	}
+}
+
 #[tauri::command]
-pub fn preview_gma(path: Option<PathBuf>) -> Result<Option<Vec<GMAEntry>>, GMAError> {
+pub fn preview_gma(path: Option<PathBuf>) -> Result<Option<GMAPreview>, GMAError> {
 	if let Some(path) = path {
 		let mut lock = PREVIEW_GMA.lock();
 
@@ -16,10 +34,7 @@ pub fn preview_gma(path: Option<PathBuf>) -> Result<Option<Vec<GMAEntry>>, GMAEr
 		gma.entries()?;
 		*lock = Some(Arc::new(gma));
 
-		let mut entries: Vec<GMAEntry> = lock.as_ref().unwrap().entries.as_ref().unwrap().values().cloned().collect();
-		entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
-
-		Ok(Some(entries))
+		Ok(Some(GMAPreview::new(lock.as_ref().unwrap())))
 	} else {
 		*PREVIEW_GMA.lock() = None;
 		Ok(None)
@@ -51,7 +66,67 @@ pub fn extract_preview_entry(gma_path: PathBuf, entry_path: String) -> Option<u3
 }
 
 #[tauri::command]
-pub fn extract_preview_gma(gma_path: PathBuf, dest: ExtractDestination) -> Option<u32> {
+pub fn edit_preview_gma(gma_path: PathBuf, add: Vec<(String, PathBuf)>, replace: Vec<(String, PathBuf)>, remove: Vec<String>) -> Option<u32> {
+	let mut lock = PREVIEW_GMA.lock();
+	if let Some(gma) = lock.as_mut() {
+		if *gma.path != gma_path {
+			let mut race_gma = GMAFile::open(gma_path).ok()?;
+			race_gma.entries().ok()?;
+			*gma = Arc::new(race_gma);
+		}
+
+		let transaction = transaction!();
+		let id = transaction.id;
+
+		let gma_ref = gma.clone();
+		rayon::spawn(move || {
+			let result = (|| {
+				let mut editor = GMAEditor::new(&gma_ref);
+				for (entry_path, path) in add {
+					editor.add(entry_path, GMAEditSource::File(path))?;
+				}
+				for (entry_path, path) in replace {
+					editor.replace(entry_path, GMAEditSource::File(path))?;
+				}
+				for entry_path in remove {
+					editor.remove(entry_path)?;
+				}
+				editor.save(&transaction)?;
+
+				let mut gma = GMAFile::open(&gma_ref.path)?;
+				gma.entries()?;
+				Ok::<_, GMAError>(gma)
+			})();
+
+			match result {
+				Ok(gma) => {
+					let preview = GMAPreview::new(&gma);
+
+					let mut lock = PREVIEW_GMA.lock();
+					if let Some(current) = lock.as_mut() {
+						if current.path == gma.path {
+							*current = Arc::new(gma);
+						}
+					}
+
+					transaction.finished(preview);
+				}
+				Err(error) => {
+					if !transaction.aborted() {
+						transaction.error(error.to_string(), turbonone!());
+					}
+				}
+			}
+		});
+
+		Some(id)
+	} else {
+		None
+	}
+}
+
+#[tauri::command]
+pub fn extract_preview_gma(gma_path: PathBuf, dest: ExtractDestination, filter: Option<ExtractFilter>) -> Option<u32> {
 	let mut lock = PREVIEW_GMA.lock();
 	if let Some(gma) = lock.as_mut() {
 		if *gma.path != gma_path {
@@ -65,7 +140,7 @@ pub fn extract_preview_gma(gma_path: PathBuf, dest: ExtractDestination) -> Optio
 
 		let gma_ref = gma.clone();
 		rayon::spawn(move || {
-			ignore! { gma_ref.extract(dest, &transaction, true, true) };
+			ignore! { gma_ref.extract_filtered(dest, &filter.unwrap_or_default(), &transaction, true, true) };
 		});
 
 		Some(id)
@@ -1,31 +1,94 @@
 use std::{
-	collections::HashMap,
 	fs::File,
-	io::{BufReader, Cursor, SeekFrom},
+	io::{BufReader, Cursor, Read, Seek, SeekFrom},
+	sync::Arc,
 };
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use memmap2::Mmap;
 
-use crate::{ArcBytes, NTStringReader};
+use crate::{decode_nt_string, ArcBytes, NTStringReader};
 
-use super::{GMAEntry, GMAError, GMAFile, GMAMetadata};
+use super::{sanitize, GMAEntries, GMAEntry, GMAError, GMAFile, GMAHeader, GMALimit, GMAMetadata, GMAStructure, RejectedEntry};
 
+/// Reads a field of `$structure`, turning a failed read into a `GMAError::FormatError` at the offset the field starts at
 macro_rules! safe_read {
-	( $x:expr ) => {
-		$x.map_err(|_| GMAError::FormatError)
-	};
+	( $handle:ident, $structure:expr, $x:expr ) => {{
+		let offset = $handle.stream_position()?;
+		$x.map_err(|_| GMAError::format($structure, offset))
+	}};
+}
+
+/// Bounds on what a GMA may declare, so parsing an untrusted GMA can't exhaust memory
+#[derive(Debug, Clone)]
+pub struct GMAParseLimits {
+	pub max_entries: usize,
+	pub max_path_len: usize,
+	/// Applies to the title, description, author and required content
+	pub max_string_len: usize,
+	pub max_required_content: usize,
+	/// Keeps entries that run past the end of the file instead of failing with `GMALimit::EntriesSize`, so they can be reported by `verify`
+	pub allow_truncated: bool,
+}
+impl Default for GMAParseLimits {
+	fn default() -> Self {
+		Self {
+			max_entries: 1 << 20,
+			max_path_len: 1024,
+			max_string_len: 1024 * 1024,
+			max_required_content: 1024,
+			allow_truncated: false,
+		}
+	}
+}
+
+/// Reads a string of `structure`, failing with `limit` if it's longer than `max_len`
+fn read_limited_string(handle: &mut GMAReader, structure: GMAStructure, limit: GMALimit, max_len: usize) -> Result<String, GMAError> {
+	let offset = handle.stream_position()?;
+	handle.read_nt_string_limited(max_len).map_err(|error| match error.kind() {
+		std::io::ErrorKind::InvalidData => GMAError::limit(limit, offset),
+		_ => GMAError::format(structure, offset),
+	})
+}
+
+#[derive(Clone)]
+pub struct ArcMmap(Arc<Mmap>);
+impl AsRef<[u8]> for ArcMmap {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
 }
 
 pub enum GMAReader {
 	MemBuffer(Cursor<ArcBytes>),
+	Mmap(Cursor<ArcMmap>),
 	Disk(BufReader<File>),
 }
+impl GMAReader {
+	/// Reads through `mmap` if `f` could be mapped, otherwise falls back to reading `f` through a buffer
+	fn mapped_or_disk(f: File, mmap: Result<Mmap, std::io::Error>) -> GMAReader {
+		match mmap {
+			Ok(mmap) => Self::Mmap(Cursor::new(ArcMmap(Arc::new(mmap)))),
+			Err(_) => Self::Disk(BufReader::new(f)),
+		}
+	}
+
+	/// Returns a new handle with its own position over the same memory, if this reader doesn't need its own file handle
+	pub fn share(&self) -> Option<GMAReader> {
+		match self {
+			Self::MemBuffer(buf) => Some(Self::MemBuffer(Cursor::new(buf.get_ref().clone()))),
+			Self::Mmap(buf) => Some(Self::Mmap(Cursor::new(buf.get_ref().clone()))),
+			Self::Disk(_) => None,
+		}
+	}
+}
 impl std::ops::Deref for GMAReader {
 	type Target = dyn NTStringReader;
 
 	fn deref(&self) -> &Self::Target {
 		match self {
 			Self::MemBuffer(buf) => buf,
+			Self::Mmap(buf) => buf,
 			Self::Disk(buf) => buf,
 		}
 	}
@@ -34,23 +97,99 @@ impl std::ops::DerefMut for GMAReader {
 	fn deref_mut(&mut self) -> &mut Self::Target {
 		match self {
 			Self::MemBuffer(buf) => buf,
+			Self::Mmap(buf) => buf,
 			Self::Disk(buf) => buf,
 		}
 	}
 }
 impl NTStringReader for Cursor<ArcBytes> {}
+impl NTStringReader for Cursor<ArcMmap> {}
 impl NTStringReader for BufReader<File> {}
 
+/// A `Read + Seek` handle over the contents of a single GMA entry. Positions are relative to the start of the entry.
+pub struct GMAEntryReader {
+	handle: GMAReader,
+	start: u64,
+	size: u64,
+	pos: u64,
+}
+impl GMAEntryReader {
+	fn new(mut handle: GMAReader, entry: &GMAEntry) -> Result<Self, std::io::Error> {
+		handle.seek(SeekFrom::Start(entry.offset))?;
+		Ok(Self {
+			handle,
+			start: entry.offset,
+			size: entry.size,
+			pos: 0,
+		})
+	}
+
+	pub fn size(&self) -> u64 {
+		self.size
+	}
+}
+impl Read for GMAEntryReader {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let remaining = self.size.saturating_sub(self.pos);
+		if remaining == 0 || buf.is_empty() {
+			return Ok(0);
+		}
+
+		let want = remaining.min(buf.len() as u64) as usize;
+		let read = self.handle.read(&mut buf[..want])?;
+		self.pos += read as u64;
+		Ok(read)
+	}
+}
+impl Seek for GMAEntryReader {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		let pos = match pos {
+			SeekFrom::Start(pos) => Some(pos),
+			SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+		}
+		.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+
+		// Seeking past the end is allowed, reads will just return nothing
+		self.handle.seek(SeekFrom::Start(self.start.saturating_add(pos.min(self.size))))?;
+		self.pos = pos;
+
+		Ok(pos)
+	}
+}
+
 impl GMAFile {
 	pub fn read(&self) -> Result<GMAReader, GMAError> {
 		if let Some(ref membuffer) = self.membuffer {
-			Ok(GMAReader::MemBuffer(Cursor::new(membuffer.clone())))
-		} else {
-			Ok(GMAReader::Disk(BufReader::new(File::open(&self.path)?)))
+			return Ok(GMAReader::MemBuffer(Cursor::new(membuffer.clone())));
 		}
+
+		let f = File::open(&self.path)?;
+
+		// SAFETY: the map is read-only. If the GMA is truncated by something else while it's mapped we can fault,
+		// which is the same tradeoff every other mmap consumer makes.
+		let mmap = unsafe { Mmap::map(&f) };
+
+		Ok(GMAReader::mapped_or_disk(f, mmap))
+	}
+
+	/// Opens a handle over the contents of the entry at `path`, without extracting it
+	pub fn open_entry(&self, path: &str) -> Result<GMAEntryReader, GMAError> {
+		let entry = self
+			.entries
+			.as_ref()
+			.expect("Expected entries to be read by this point")
+			.get_path(path)
+			.ok_or(GMAError::EntryNotFound)?;
+
+		Ok(GMAEntryReader::new(self.read()?, entry)?)
 	}
 
 	pub fn metadata(&mut self) -> Result<Option<GMAReader>, GMAError> {
+		self.metadata_with_limits(&GMAParseLimits::default())
+	}
+
+	pub fn metadata_with_limits(&mut self, limits: &GMAParseLimits) -> Result<Option<GMAReader>, GMAError> {
 		main_thread_forbidden!();
 
 		if self.metadata.is_some() {
@@ -59,16 +198,26 @@ impl GMAFile {
 			let mut handle = self.read()?;
 			handle.seek(SeekFrom::Start(self.pointers.metadata))?;
 
-			safe_read!(handle.read_u64::<LittleEndian>())?; // steamid [unused]
-			safe_read!(handle.read_u64::<LittleEndian>())?; // timestamp
+			let steamid = safe_read!(handle, GMAStructure::Header, handle.read_u64::<LittleEndian>())?;
+			let timestamp = safe_read!(handle, GMAStructure::Header, handle.read_u64::<LittleEndian>())?;
 
+			let mut required_content = Vec::new();
 			if self.version > 1 {
-				// required content [unused]
-				safe_read!(handle.skip_nt_string())?;
+				loop {
+					let offset = handle.stream_position()?;
+					let content = read_limited_string(&mut handle, GMAStructure::Header, GMALimit::StringLength, limits.max_string_len)?;
+					if content.is_empty() {
+						break;
+					}
+					if required_content.len() >= limits.max_required_content {
+						return Err(GMAError::limit(GMALimit::RequiredContentCount, offset));
+					}
+					required_content.push(content);
+				}
 			}
 
-			let embedded_title = safe_read!(handle.read_nt_string())?;
-			let embedded_description = safe_read!(handle.read_nt_string())?;
+			let embedded_title = read_limited_string(&mut handle, GMAStructure::Metadata, GMALimit::StringLength, limits.max_string_len)?;
+			let embedded_description = read_limited_string(&mut handle, GMAStructure::Metadata, GMALimit::StringLength, limits.max_string_len)?;
 
 			self.metadata = Some(match serde_json::de::from_str::<GMAMetadata>(&embedded_description) {
 				Ok(mut metadata) => {
@@ -87,8 +236,16 @@ impl GMAFile {
 				},
 			});
 
-			safe_read!(handle.skip_nt_string())?; // author [unused]
-			safe_read!(handle.read_i32::<LittleEndian>())?; // addon version [unused]
+			let author = read_limited_string(&mut handle, GMAStructure::Metadata, GMALimit::StringLength, limits.max_string_len)?;
+			let addon_version = safe_read!(handle, GMAStructure::Metadata, handle.read_i32::<LittleEndian>())?;
+
+			self.header = Some(GMAHeader {
+				steamid,
+				timestamp,
+				required_content,
+				author,
+				addon_version,
+			});
 
 			self.pointers.entries_list = handle.seek(SeekFrom::Current(0))?;
 
@@ -101,73 +258,330 @@ impl GMAFile {
 	// https://steamcommunity.com/sharedfiles/filedetails/?id=1727993520
 
 	pub fn entries(&mut self) -> Result<Option<GMAReader>, GMAError> {
+		self.entries_with_limits(&GMAParseLimits::default())
+	}
+
+	pub fn entries_with_limits(&mut self, limits: &GMAParseLimits) -> Result<Option<GMAReader>, GMAError> {
 		main_thread_forbidden!();
 
 		if self.entries.is_some() {
 			Ok(None)
 		} else {
-			let mut handle = match self.metadata()? {
+			let mut handle = match self.metadata_with_limits(limits)? {
 				Some(handle) => handle,
 				None => self.read()?,
 			};
+			let file_len = crate::stream_len(&mut *handle)?;
 			handle.seek(SeekFrom::Start(self.pointers.entries_list))?;
 
-			let mut entries = HashMap::new();
-			let mut entry_cursor = 0;
-
-			'read_entries: while handle.read_u32::<LittleEndian>()? != 0 {
-				let path = handle.read_nt_string()?;
-				let size = handle.read_i64::<LittleEndian>()? as u64;
-				let crc = handle.read_u32::<LittleEndian>()?;
-
-				// Detect ../ and skip this entry if found to prevent directory traversal attack
-				{
-					let mut dots = 0;
-					for byte in path.as_bytes() {
-						const DOT: u8 = b'.';
-						const FORWARDS_SLASH: u8 = b'/';
-						const BACKWARDS_SLASH: u8 = b'\\';
-
-						match *byte {
-							DOT => {
-								if dots == 2 {
-									dots = 0;
-								} else {
-									dots += 1;
-								}
-							}
-							FORWARDS_SLASH | BACKWARDS_SLASH => {
-								if dots == 2 {
-									eprintln!("Illegal GMA entry: {}", path);
-									continue 'read_entries;
-								} else {
-									dots = 0;
-								}
-							}
-							_ => dots = 0,
-						}
-					}
+			let mut entries = Vec::new();
+			let mut rejected = Vec::new();
+			let mut entry_count: usize = 0;
+			let mut entry_cursor: u64 = 0;
+
+			loop {
+				let offset = handle.stream_position()?;
+				if safe_read!(handle, GMAStructure::Index, handle.read_u32::<LittleEndian>())? == 0 {
+					break;
 				}
 
-				let entry = GMAEntry {
-					path: path.clone(),
-					size,
-					crc,
-					index: entry_cursor,
-				};
+				entry_count += 1;
+				if entry_count > limits.max_entries {
+					return Err(GMAError::limit(GMALimit::EntryCount, offset));
+				}
 
+				let path_offset = handle.stream_position()?;
+				let raw_path = handle.read_nt_bytes_limited(limits.max_path_len).map_err(|error| match error.kind() {
+					std::io::ErrorKind::InvalidData => GMAError::limit(GMALimit::PathLength, path_offset),
+					_ => GMAError::format(GMAStructure::Index, path_offset),
+				})?;
+				let path = decode_nt_string(&raw_path);
+				let raw_path = if raw_path == path.as_bytes() { None } else { Some(raw_path) };
+				let offset = handle.stream_position()?;
+				let size = handle
+					.read_i64::<LittleEndian>()
+					.map_err(|_| GMAError::format(GMAStructure::Index, offset).with_entry(path.as_str()))? as u64;
+				let crc =
+					safe_read!(handle, GMAStructure::Index, handle.read_u32::<LittleEndian>()).map_err(|error| error.with_entry(path.as_str()))?;
+
+				let index = entry_cursor;
 				entry_cursor = match entry_cursor.checked_add(size) {
-					None => return Err(GMAError::FormatError),
+					None => return Err(GMAError::format(GMAStructure::Index, offset).with_entry(path)),
 					Some(entry_cursor) => entry_cursor,
 				};
+				if entry_cursor > file_len && !limits.allow_truncated {
+					return Err(GMAError::limit(GMALimit::EntriesSize, offset).with_entry(path));
+				}
+
+				// Skip entries that could escape the directory they're extracted to
+				if let Err(reason) = sanitize::check_entry(&path, raw_path.as_deref()) {
+					eprintln!("Illegal GMA entry ({:?}): {}", reason, path);
+					rejected.push(RejectedEntry { path, reason });
+					continue;
+				}
 
-				entries.insert(path, entry);
+				entries.push((path, raw_path, size, crc, index));
 			}
 
-			self.entries = Some(entries);
 			self.pointers.entries = handle.seek(SeekFrom::Current(0))?;
 
+			if self.pointers.entries.saturating_add(entry_cursor) > file_len && !limits.allow_truncated {
+				return Err(GMAError::limit(GMALimit::EntriesSize, self.pointers.entries));
+			}
+
+			// Offsets are only known once we've found the end of the entries list
+			let mut index = GMAEntries {
+				rejected,
+				..Default::default()
+			};
+			for (path, raw_path, size, crc, entry_index) in entries {
+				index.push(GMAEntry {
+					path,
+					size,
+					crc,
+					offset: self.pointers.entries + entry_index,
+					duplicate: false,
+					raw_path,
+				});
+			}
+			self.entries = Some(index);
+
 			Ok(Some(handle))
 		}
 	}
 }
+
+#[test]
+fn test_entries_order() {
+	let contents: [(&str, &[u8]); 3] = [
+		("lua/autorun/b.lua", b"first"),
+		("lua/autorun/a.lua", b"second"),
+		("lua/autorun/b.lua", b"third"),
+	];
+	let bytes = super::test_gma_unchecked(&contents);
+
+	let mut gma = GMAFile::open_bytes(bytes.clone(), "order.gma").unwrap();
+	gma.entries().unwrap();
+	let entries = gma.entries.as_ref().unwrap();
+
+	assert_eq!(
+		entries.iter().map(|entry| (entry.path.as_str(), entry.duplicate)).collect::<Vec<_>>(),
+		[("lua/autorun/b.lua", true), ("lua/autorun/a.lua", false), ("lua/autorun/b.lua", true)]
+	);
+	for (entry, (_, data)) in entries.iter().zip(contents.iter()) {
+		assert_eq!(&bytes[entry.offset as usize..(entry.offset + entry.size) as usize], *data);
+	}
+	assert_eq!(entries.duplicates().count(), 2);
+
+	// The last entry with a path is the one that counts
+	assert_eq!(entries.get_path("lua/autorun/b.lua").unwrap().offset, entries[2].offset);
+	assert_eq!(
+		entries.unique().map(|entry| entry.offset).collect::<Vec<_>>(),
+		[entries[1].offset, entries[2].offset]
+	);
+
+	let mut read = String::new();
+	gma.open_entry("lua/autorun/b.lua").unwrap().read_to_string(&mut read).unwrap();
+	assert_eq!(read, "third");
+}
+
+#[test]
+fn test_entry_reader_seek() {
+	let bytes = super::test_gma(&[("lua/autorun/a.lua", b"0123456789"), ("lua/autorun/b.lua", b"abcdef")]);
+	let mut gma = GMAFile::open_bytes(bytes, "seek.gma").unwrap();
+	gma.entries().unwrap();
+
+	assert!(matches!(gma.open_entry("lua/autorun/c.lua"), Err(GMAError::EntryNotFound)));
+
+	let mut r = gma.open_entry("lua/autorun/a.lua").unwrap();
+	assert_eq!(r.size(), 10);
+
+	let mut buf = [0u8; 4];
+	assert_eq!(r.seek(SeekFrom::Start(3)).unwrap(), 3);
+	r.read_exact(&mut buf).unwrap();
+	assert_eq!(&buf, b"3456");
+
+	assert_eq!(r.seek(SeekFrom::Current(-2)).unwrap(), 5);
+	r.read_exact(&mut buf).unwrap();
+	assert_eq!(&buf, b"5678");
+
+	// Reads stop at the end of the entry instead of running into the next one
+	assert_eq!(r.seek(SeekFrom::End(-2)).unwrap(), 8);
+	let mut rest = Vec::new();
+	r.read_to_end(&mut rest).unwrap();
+	assert_eq!(rest, b"89");
+
+	assert_eq!(r.seek(SeekFrom::End(5)).unwrap(), 15);
+	assert_eq!(r.read(&mut buf).unwrap(), 0);
+
+	assert_eq!(r.seek(SeekFrom::Current(-100)).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+	r.rewind().unwrap();
+	let mut all = Vec::new();
+	r.read_to_end(&mut all).unwrap();
+	assert_eq!(all, b"0123456789");
+}
+
+#[test]
+fn test_mmap_and_disk_fallback() {
+	let contents: [(&str, &[u8]); 2] = [("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")];
+
+	let dir = super::test_dir("mmap_and_disk_fallback");
+	let path = dir.join("test.gma");
+	std::fs::write(&path, super::test_gma(&contents)).unwrap();
+
+	let mut gma = GMAFile::open(&path).unwrap();
+	gma.entries().unwrap();
+	let entries = gma.entries.clone().unwrap();
+
+	// Workers share the map, each with their own position
+	let mut mapped = gma.read().unwrap();
+	assert!(matches!(mapped, GMAReader::Mmap(_)));
+	let mut shared = mapped.share().unwrap();
+	shared.seek(SeekFrom::Start(entries[1].offset)).unwrap();
+	assert_eq!(mapped.stream_position().unwrap(), 0);
+
+	let disk = GMAReader::mapped_or_disk(File::open(&path).unwrap(), Err(std::io::ErrorKind::Unsupported.into()));
+	assert!(matches!(disk, GMAReader::Disk(_)));
+	assert!(disk.share().is_none());
+
+	// Both read the same contents
+	for (entry, (_, expected)) in entries.iter().zip(contents.iter()) {
+		for handle in [
+			gma.read().unwrap(),
+			GMAReader::mapped_or_disk(File::open(&path).unwrap(), Err(std::io::ErrorKind::Unsupported.into())),
+		] {
+			let mut read = Vec::new();
+			GMAEntryReader::new(handle, entry).unwrap().read_to_end(&mut read).unwrap();
+			assert_eq!(read, *expected);
+		}
+	}
+
+	// Extraction shares the map between its workers
+	let report = super::ExtractGMAImmut::extract(&gma, super::ExtractDestination::Directory(dir.join("out")), &transaction!(), false, true);
+	let extracted = contents
+		.iter()
+		.map(|(entry_path, _)| std::fs::read(dir.join("out").join(entry_path)).ok())
+		.collect::<Vec<_>>();
+
+	std::fs::remove_dir_all(&dir).ok();
+
+	assert_eq!(report.unwrap().extracted.len(), 2);
+	assert_eq!(
+		extracted,
+		contents.iter().map(|(_, expected)| Some(expected.to_vec())).collect::<Vec<_>>()
+	);
+}
+
+#[test]
+fn test_parse_limits() {
+	use super::{write::write_header, GMAWriter};
+
+	let header = |required_content: &[&str], title: &str| {
+		write_header(
+			title,
+			"Description",
+			&GMAHeader {
+				required_content: required_content.iter().map(|content| content.to_string()).collect(),
+				..Default::default()
+			},
+		)
+		.unwrap()
+	};
+	let gma = |header: Vec<u8>, entries: &[(&str, &[u8])]| {
+		let mut w = GMAWriter::new(
+			Cursor::new(Vec::new()),
+			header,
+			entries.iter().map(|(path, data)| (*path, data.len() as u64)),
+		)
+		.unwrap();
+		for (_, data) in entries {
+			w.write_entry(&mut &data[..]).unwrap();
+		}
+		w.finish().unwrap().into_inner()
+	};
+	let parse = |bytes: &[u8], limits: GMAParseLimits| {
+		GMAFile::open_bytes(bytes.to_vec(), "limits.gma")
+			.unwrap()
+			.entries_with_limits(&limits)
+			.map(|_| ())
+	};
+	let exceeded = |result: Result<(), GMAError>| match result {
+		Err(GMAError::LimitExceeded { limit, offset, .. }) => Some((limit, offset)),
+		_ => None,
+	};
+
+	// The magic, version, steamid and timestamp come before the required content
+	const REQUIRED_CONTENT: u64 = 4 + 1 + 8 + 8;
+
+	let entries: [(&str, &[u8]); 2] = [("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")];
+	let entries_list = header(&[], "Test").len() as u64;
+	let bytes = gma(header(&[], "Test"), &entries);
+
+	let required_content = gma(header(&["123", "456"], "Test"), &entries);
+	assert_eq!(
+		exceeded(parse(
+			&required_content,
+			GMAParseLimits {
+				max_required_content: 1,
+				..Default::default()
+			}
+		)),
+		Some((GMALimit::RequiredContentCount, REQUIRED_CONTENT + 4))
+	);
+
+	// The title comes right after the empty string that ends the required content
+	let long_title = gma(header(&[], "A very long title"), &entries);
+	assert_eq!(
+		exceeded(parse(
+			&long_title,
+			GMAParseLimits {
+				max_string_len: 8,
+				..Default::default()
+			}
+		)),
+		Some((GMALimit::StringLength, REQUIRED_CONTENT + 1))
+	);
+
+	assert_eq!(
+		exceeded(parse(
+			&bytes,
+			GMAParseLimits {
+				max_entries: 1,
+				..Default::default()
+			}
+		)),
+		Some((GMALimit::EntryCount, entries_list + 4 + entries[0].0.len() as u64 + 1 + 8 + 4))
+	);
+
+	assert_eq!(
+		exceeded(parse(
+			&bytes,
+			GMAParseLimits {
+				max_path_len: 8,
+				..Default::default()
+			}
+		)),
+		Some((GMALimit::PathLength, entries_list + 4))
+	);
+
+	// Cutting off the last entry's data means the entries add up to more than the file
+	let truncated = &bytes[..bytes.len() - 4 - entries[1].1.len()];
+	let entries_start = bytes.len() as u64 - 4 - entries.iter().map(|(_, data)| data.len() as u64).sum::<u64>();
+	assert_eq!(
+		exceeded(parse(truncated, GMAParseLimits::default())),
+		Some((GMALimit::EntriesSize, entries_start))
+	);
+	assert!(parse(
+		truncated,
+		GMAParseLimits {
+			allow_truncated: true,
+			..Default::default()
+		}
+	)
+	.is_ok());
+
+	for bytes in [&bytes, &required_content, &long_title] {
+		assert!(parse(bytes, GMAParseLimits::default()).is_ok());
+	}
+}
@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::GMAEntry;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum UnsafePathReason {
+	Empty,
+	NulByte,
+	/// Starts with a slash, which would make it relative to the root of the drive
+	Absolute,
+	/// Starts with a Windows drive letter, e.g. `C:`
+	DrivePrefix,
+	ParentDirectory,
+	/// Contains a colon, which on NTFS names an alternate data stream of a file instead of a file
+	AlternateDataStream,
+	/// A component is a reserved Windows device name, e.g. `CON` or `NUL.txt`. Only rejected when extracting on Windows.
+	ReservedName,
+	/// A component ends with a dot or a space, which Windows silently strips. Only rejected when extracting on Windows.
+	TrailingDotOrSpace,
+	/// The destination is reached through a symlink that points outside of the directory being extracted to
+	Symlink,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedEntry {
+	pub path: String,
+	pub reason: UnsafePathReason,
+}
+
+const RESERVED_NAMES: &[&str] = &[
+	"CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5",
+	"LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn is_reserved_name(component: &str) -> bool {
+	let stem = match component.split_once('.') {
+		Some((stem, _)) => stem,
+		None => component,
+	};
+	RESERVED_NAMES.iter().any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+}
+
+/// Checks that a GMA entry path is relative and stays inside whatever directory it's extracted to, on every platform.
+/// Names that are only a problem on Windows are left to `check_windows_path`, so they don't stop the GMA being read elsewhere.
+pub fn check_entry_path(path: &str) -> Result<(), UnsafePathReason> {
+	if path.is_empty() {
+		return Err(UnsafePathReason::Empty);
+	}
+	if path.contains('\0') {
+		return Err(UnsafePathReason::NulByte);
+	}
+	if path.starts_with('/') || path.starts_with('\\') {
+		return Err(UnsafePathReason::Absolute);
+	}
+
+	let bytes = path.as_bytes();
+	if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+		return Err(UnsafePathReason::DrivePrefix);
+	}
+
+	if path.contains(':') {
+		return Err(UnsafePathReason::AlternateDataStream);
+	}
+
+	for component in path.split(['/', '\\']) {
+		if component == ".." {
+			return Err(UnsafePathReason::ParentDirectory);
+		}
+	}
+
+	Ok(())
+}
+
+/// Checks for names Windows can't create files with, or would create a different file for
+pub fn check_windows_path(path: &str) -> Result<(), UnsafePathReason> {
+	for component in path.split(['/', '\\']) {
+		if matches!(component, "" | "." | "..") {
+			continue;
+		}
+
+		if component.ends_with('.') || component.ends_with(' ') {
+			return Err(UnsafePathReason::TrailingDotOrSpace);
+		}
+
+		if is_reserved_name(component) {
+			return Err(UnsafePathReason::ReservedName);
+		}
+	}
+
+	Ok(())
+}
+
+/// Like `check_entry_path`, but also checks the raw bytes of paths that aren't UTF-8, which are what ends up on disk on some platforms
+pub fn check_entry(path: &str, raw_path: Option<&[u8]>) -> Result<(), UnsafePathReason> {
+	check_entry_path(path)?;
+	if let Some(raw_path) = raw_path {
+		check_entry_path(&String::from_utf8_lossy(raw_path))?;
+	}
+	Ok(())
+}
+
+/// The path `entry` is extracted to, relative to the directory it's extracted to.
+/// Paths that aren't UTF-8 keep their raw bytes on platforms that allow it.
+pub fn entry_fs_path(entry: &GMAEntry) -> PathBuf {
+	#[cfg(unix)]
+	if let Some(raw_path) = &entry.raw_path {
+		use std::os::unix::ffi::OsStrExt;
+		return PathBuf::from(std::ffi::OsStr::from_bytes(raw_path));
+	}
+	PathBuf::from(&entry.path)
+}
+
+/// Joins a GMA entry path onto `dest`, making sure the result can't escape `dest`, including through symlinks that already exist inside it
+pub fn safe_join(dest: &Path, entry_path: &str) -> Result<PathBuf, UnsafePathReason> {
+	check_entry_path(entry_path)?;
+	if cfg!(windows) {
+		check_windows_path(entry_path)?;
+	}
+	join_checked(dest, Path::new(entry_path))
+}
+
+/// Like `safe_join`, for the path `entry_fs_path` gives
+pub fn safe_join_entry(dest: &Path, entry: &GMAEntry) -> Result<PathBuf, UnsafePathReason> {
+	check_entry(&entry.path, entry.raw_path.as_deref())?;
+	if cfg!(windows) {
+		check_windows_path(&entry.path)?;
+	}
+	join_checked(dest, &entry_fs_path(entry))
+}
+
+fn join_checked(dest: &Path, relative_path: &Path) -> Result<PathBuf, UnsafePathReason> {
+	let joined = dest.join(relative_path);
+
+	// Nothing can be symlinked inside a directory that doesn't exist yet
+	let dest_canonical = match dunce::canonicalize(dest) {
+		Ok(dest_canonical) => dest_canonical,
+		Err(_) => return Ok(joined),
+	};
+
+	let mut path = dest.to_path_buf();
+	for component in relative_path.components() {
+		path.push(component);
+
+		match path.symlink_metadata() {
+			Ok(metadata) if metadata.file_type().is_symlink() => match dunce::canonicalize(&path) {
+				Ok(target) if target.starts_with(&dest_canonical) => {}
+				_ => return Err(UnsafePathReason::Symlink),
+			},
+			Ok(_) => {}
+			// The rest of the path doesn't exist yet, so it can't contain any symlinks
+			Err(_) => break,
+		}
+	}
+
+	Ok(joined)
+}
+
+#[test]
+fn test_check_entry_path() {
+	let good: &[&str] = &[
+		"lua/autorun/test.lua",
+		"materials/con_thing.vmt",
+		"materials/console/bg.vtf",
+		"sound/nul_/a.wav",
+		"models/a.b.mdl",
+	];
+	for path in good {
+		assert_eq!(check_entry_path(path), Ok(()), "{}", path);
+	}
+
+	let bad: &[(&str, UnsafePathReason)] = &[
+		("", UnsafePathReason::Empty),
+		("lua/\0.lua", UnsafePathReason::NulByte),
+		("/etc/passwd", UnsafePathReason::Absolute),
+		("\\windows\\system32", UnsafePathReason::Absolute),
+		("C:/windows/system32", UnsafePathReason::DrivePrefix),
+		("c:test.lua", UnsafePathReason::DrivePrefix),
+		("lua/../../test.lua", UnsafePathReason::ParentDirectory),
+		("lua\\..\\test.lua", UnsafePathReason::ParentDirectory),
+		("lua/test.lua:stream", UnsafePathReason::AlternateDataStream),
+		("lua/test.lua::$DATA", UnsafePathReason::AlternateDataStream),
+	];
+	for (path, reason) in bad {
+		assert_eq!(check_entry_path(path), Err(*reason), "{}", path);
+	}
+}
+
+#[test]
+fn test_check_windows_path() {
+	let bad: &[(&str, UnsafePathReason)] = &[
+		("lua/con/test.lua", UnsafePathReason::ReservedName),
+		("lua/NUL.txt", UnsafePathReason::ReservedName),
+		("lua/com1", UnsafePathReason::ReservedName),
+		("lua/test.lua.", UnsafePathReason::TrailingDotOrSpace),
+		("lua /test.lua", UnsafePathReason::TrailingDotOrSpace),
+		("lua/.../test.lua", UnsafePathReason::TrailingDotOrSpace),
+	];
+	for (path, reason) in bad {
+		// Only Windows has a problem with these
+		assert_eq!(check_entry_path(path), Ok(()), "{}", path);
+		assert_eq!(check_windows_path(path), Err(*reason), "{}", path);
+	}
+
+	assert_eq!(check_windows_path("materials/con_thing.vmt"), Ok(()));
+	assert_eq!(check_windows_path("lua/./test.lua"), Ok(()));
+}
+
+#[test]
+fn test_rejected_entries_not_rewritten() {
+	use super::{GMAEditor, GMAError, GMAFile, GMAMergePolicy};
+
+	let bytes = super::test_gma_unchecked(&[("lua/autorun/a.lua", b"print('a')"), ("lua/../../evil.lua", b"print('evil')")]);
+	let open = || {
+		let mut gma = GMAFile::open_bytes(bytes.clone(), "rejected.gma").unwrap();
+		gma.entries().unwrap();
+		gma
+	};
+
+	let gma = open();
+	assert_eq!(gma.entries.as_ref().unwrap().len(), 1);
+	assert_eq!(gma.entries.as_ref().unwrap().rejected()[0].reason, UnsafePathReason::ParentDirectory);
+
+	let dir = super::test_dir("rejected_entries_not_rewritten");
+	let mut editor = GMAEditor::new(&gma);
+	editor.remove("lua/autorun/a.lua").unwrap();
+	let edited = editor.write_to(dir.join("edited.gma"), &transaction!());
+	let merged = GMAFile::merge(&mut [open()], dir.join("merged.gma"), GMAMergePolicy::Fail, &transaction!());
+	let split = open().split(dir.join("split.gma"), 1024 * 1024, &transaction!());
+	let written = std::fs::read_dir(&dir).unwrap().count();
+
+	std::fs::remove_dir_all(&dir).ok();
+
+	assert!(matches!(edited, Err(GMAError::RejectedEntries(ref rejected)) if rejected[0].path == "lua/../../evil.lua"));
+	assert!(matches!(merged, Err(GMAError::RejectedEntries(_))));
+	assert!(matches!(split, Err(GMAError::RejectedEntries(_))));
+	assert_eq!(written, 0);
+
+	// Names that are only a problem on Windows are still read everywhere
+	let mut gma = GMAFile::open_bytes(super::test_gma_unchecked(&[("lua/con.lua", b"print('con')")]), "con.gma").unwrap();
+	gma.entries().unwrap();
+	assert!(gma.entries.as_ref().unwrap().rejected().is_empty());
+	assert_eq!(
+		safe_join_entry(Path::new("out"), &gma.entries.as_ref().unwrap()[0]).is_err(),
+		cfg!(windows)
+	);
+}
@@ -0,0 +1,270 @@
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::transactions::Transaction;
+
+use super::{whitelist, write::source_entries, GMABuilder, GMABuilderSource, GMACreateOptions, GMAError, GMAFile, GMAHeader, GMAMetadata};
+
+/// Files of a model that have to be in the same GMA as the .mdl
+const MODEL_EXTENSIONS: &[&str] = &["mdl", "vvd", "phy", "vtx", "ani"];
+/// Files of a material that have to be in the same GMA as the .vmt
+const MATERIAL_EXTENSIONS: &[&str] = &["vmt", "vtf"];
+
+/// Bytes every entry adds to the entries list on top of its path: index, null terminator, size and CRC
+const ENTRY_OVERHEAD: u64 = 4 + 1 + 8 + 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GMASplitPart {
+	pub path: PathBuf,
+	pub title: String,
+	pub entries: Vec<String>,
+	pub size: u64,
+}
+
+struct SplitItem<'a> {
+	path: String,
+	/// Path bytes of a GMA entry whose path isn't UTF-8
+	raw_path: Option<Vec<u8>>,
+	size: u64,
+	source: GMABuilderSource<'a>,
+}
+
+/// Everything that has to end up in the same GMA as `path`: a model's .mdl/.vvd/.phy/.vtx files, or a material's .vmt and .vtf
+fn group_key(path: &str) -> String {
+	let (dir, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+	let (stem, _) = file_name.split_once('.').unwrap_or((file_name, ""));
+	let extension = file_name.rsplit_once('.').map(|(_, extension)| extension).unwrap_or("");
+
+	if MODEL_EXTENSIONS.contains(&extension) {
+		format!("model:{}/{}", dir, stem)
+	} else if MATERIAL_EXTENSIONS.contains(&extension) {
+		format!("material:{}/{}", dir, stem)
+	} else {
+		path.to_owned()
+	}
+}
+
+fn part_metadata(metadata: &GMAMetadata, title: String) -> GMAMetadata {
+	let mut metadata = metadata.clone();
+	match &mut metadata {
+		GMAMetadata::Standard { title: old_title, .. } => *old_title = title,
+		GMAMetadata::Legacy { title: old_title, .. } => *old_title = title,
+	}
+	metadata
+}
+
+/// `dest` with `_{n}` appended to its file stem
+fn part_path(dest: &Path, n: usize) -> PathBuf {
+	let stem = dest.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+	dest.with_file_name(format!("{}_{}.gma", stem, n))
+}
+
+fn split(
+	items: Vec<SplitItem>,
+	metadata: &GMAMetadata,
+	header: &GMAHeader,
+	budget: u64,
+	dest: &Path,
+	transaction: &Transaction,
+) -> Result<Vec<GMASplitPart>, GMAError> {
+	// Header, entries list terminator and trailing CRC, plus some room for the part number in the title
+	let base_size = GMACreateOptions::default().header(metadata, header)?.len() as u64 + 4 + 4 + 64;
+
+	let mut not_whitelisted = Vec::new();
+	let mut groups: BTreeMap<String, (u64, Vec<SplitItem>)> = BTreeMap::new();
+	for item in items {
+		if !whitelist::check(&item.path) {
+			not_whitelisted.push(item.path);
+			continue;
+		}
+
+		let group = groups.entry(group_key(&item.path)).or_default();
+		let path_len = item.raw_path.as_ref().map(|raw_path| raw_path.len()).unwrap_or(item.path.len());
+		group.0 += item.size + path_len as u64 + ENTRY_OVERHEAD;
+		group.1.push(item);
+	}
+	if !not_whitelisted.is_empty() {
+		return Err(GMAError::NotWhitelistedEntries(not_whitelisted));
+	}
+
+	// First fit decreasing, biggest groups first so the small ones can fill the gaps
+	let mut groups = groups.into_iter().collect::<Vec<_>>();
+	groups.sort_by_key(|(_, group)| std::cmp::Reverse(group.0));
+
+	let mut bins: Vec<(u64, Vec<SplitItem>)> = Vec::new();
+	for (group, (size, items)) in groups {
+		// Splitting the group up would break whatever its files make up, so it can't go in a GMA of its own either
+		if base_size + size > budget {
+			return Err(GMAError::SplitGroupTooLarge { group, size });
+		}
+		match bins.iter_mut().find(|bin| bin.0 + size <= budget) {
+			Some((used, bin)) => {
+				*used += size;
+				bin.extend(items);
+			}
+			None => bins.push((base_size + size, items)),
+		}
+	}
+
+	let n = bins.len();
+	let mut parts = Vec::with_capacity(n);
+	for (i, (_, items)) in bins.into_iter().enumerate() {
+		if transaction.aborted() {
+			return Err(GMAError::Cancelled);
+		}
+
+		let title = format!("{} ({}/{})", metadata.title(), i + 1, n);
+		let path = part_path(dest, i + 1);
+
+		let mut builder = GMABuilder::new(part_metadata(metadata, title.clone()));
+		builder.header(header.clone());
+
+		let mut entries = Vec::with_capacity(items.len());
+		for item in items {
+			builder.add_raw(&item.path, item.raw_path, item.size, item.source)?;
+			entries.push(item.path);
+		}
+		entries.sort_unstable();
+
+		builder.write_to_path(&path, transaction)?;
+
+		let size = path.metadata()?.len();
+		parts.push(GMASplitPart { path, title, entries, size });
+	}
+
+	Ok(parts)
+}
+
+impl GMAFile {
+	/// Splits this GMA into GMAs of at most `budget` bytes, named after `dest` with `_1`, `_2`... appended and titled "Title (1/3)" and so on.
+	///
+	/// A model's or material's files always end up in the same GMA, so if they alone don't fit in `budget` this fails with
+	/// `GMAError::SplitGroupTooLarge`. Entries that don't pass the whitelist fail it with `GMAError::NotWhitelistedEntries`.
+	pub fn split<P: AsRef<Path>>(&mut self, dest: P, budget: u64, transaction: &Transaction) -> Result<Vec<GMASplitPart>, GMAError> {
+		main_thread_forbidden!();
+
+		self.entries()?;
+		self.entries.as_ref().unwrap().ensure_none_rejected()?;
+
+		let gma = &*self;
+		let items = gma
+			.entries
+			.as_ref()
+			.unwrap()
+			.unique()
+			.map(|entry| SplitItem {
+				path: entry.path.clone(),
+				raw_path: entry.raw_path.clone(),
+				size: entry.size,
+				source: GMABuilderSource::Entry(gma, entry.path.clone()),
+			})
+			.collect();
+
+		split(
+			items,
+			gma.metadata.as_ref().unwrap(),
+			&gma.header.clone().unwrap_or_default(),
+			budget,
+			dest.as_ref(),
+			transaction,
+		)
+	}
+
+	/// Like `GMAFile::create`, but splits the addon into GMAs of at most `budget` bytes. See `GMAFile::split`.
+	pub fn create_split<P: AsRef<Path>>(&self, src_path: P, budget: u64, transaction: &Transaction) -> Result<Vec<GMASplitPart>, GMAError> {
+		main_thread_forbidden!();
+
+		let metadata = self.metadata.as_ref().expect("Expected metadata to be set");
+
+		let items = source_entries(src_path.as_ref(), metadata.ignore().map(|ignore| ignore.as_slice()), transaction)?
+			.into_iter()
+			.map(|entry| SplitItem {
+				path: entry.relative_path,
+				raw_path: None,
+				size: entry.size,
+				source: GMABuilderSource::File(entry.path),
+			})
+			.collect();
+
+		split(items, metadata, &self.header.clone().unwrap_or_default(), budget, &self.path, transaction)
+	}
+}
+
+#[test]
+fn test_group_key() {
+	assert_eq!(group_key("models/props/crate.mdl"), group_key("models/props/crate.dx90.vtx"));
+	assert_eq!(group_key("models/props/crate.mdl"), group_key("models/props/crate.phy"));
+	assert_ne!(group_key("models/props/crate.mdl"), group_key("models/props/crate2.mdl"));
+	assert_eq!(group_key("materials/props/crate.vmt"), group_key("materials/props/crate.vtf"));
+	assert_ne!(group_key("materials/props/crate.vmt"), group_key("models/props/crate.mdl"));
+	assert_eq!(group_key("lua/autorun/crate.lua"), "lua/autorun/crate.lua");
+}
+
+#[test]
+fn test_split_raw_paths() {
+	let raw_path = b"lua/autorun/caf\xe9.lua".to_vec();
+
+	let mut builder = GMABuilder::new(super::test_metadata());
+	builder
+		.add_raw(
+			"lua/autorun/cafe.lua",
+			Some(raw_path.clone()),
+			5,
+			GMABuilderSource::Bytes(b"print".to_vec()),
+		)
+		.unwrap();
+	builder.add_bytes("lua/autorun/test.lua", b"print('test')".to_vec()).unwrap();
+	let bytes = builder.write_to(std::io::Cursor::new(Vec::new()), &transaction!()).unwrap().into_inner();
+
+	let dest = super::test_dir("split_raw_paths");
+
+	let mut gma = GMAFile::open_bytes(bytes, "raw.gma").unwrap();
+	let parts = gma.split(dest.join("raw.gma"), 1024 * 1024, &transaction!()).unwrap();
+	assert_eq!(parts.len(), 1);
+
+	let mut part = GMAFile::open(&parts[0].path).unwrap();
+	part.entries().unwrap();
+	let raw_paths = part
+		.entries
+		.as_ref()
+		.unwrap()
+		.iter()
+		.map(|entry| entry.raw_path.clone().unwrap_or_else(|| entry.path.as_bytes().to_vec()))
+		.collect::<Vec<_>>();
+
+	std::fs::remove_dir_all(&dest).ok();
+
+	assert!(raw_paths.contains(&raw_path));
+	assert!(raw_paths.contains(&b"lua/autorun/test.lua".to_vec()));
+}
+
+#[test]
+fn test_split_errors() {
+	let dest = super::test_dir("split_errors");
+
+	let mut gma = GMAFile::open_bytes(
+		super::test_gma_unchecked(&[("lua/autorun/a.lua", b"print('a')"), ("virus.exe", b"MZ")]),
+		"not_whitelisted.gma",
+	)
+	.unwrap();
+	let not_whitelisted = gma.split(dest.join("not_whitelisted.gma"), 1024 * 1024, &transaction!());
+
+	let mdl = vec![0u8; 4096];
+	let mut gma = GMAFile::open_bytes(
+		super::test_gma(&[("models/big.mdl", &mdl), ("models/big.vvd", &mdl), ("lua/autorun/a.lua", b"print('a')")]),
+		"too_large.gma",
+	)
+	.unwrap();
+	let too_large = gma.split(dest.join("too_large.gma"), 6 * 1024, &transaction!());
+
+	let written = dest.read_dir().map(|dir| dir.count()).unwrap_or(0);
+	std::fs::remove_dir_all(&dest).ok();
+
+	assert!(matches!(not_whitelisted, Err(GMAError::NotWhitelistedEntries(ref paths)) if paths == &["virus.exe"]));
+	assert!(matches!(too_large, Err(GMAError::SplitGroupTooLarge { ref group, size }) if group == "model:models/big" && size > 8192));
+	assert_eq!(written, 0);
+}
@@ -0,0 +1,257 @@
+use std::{
+	io::{Read, SeekFrom},
+	path::PathBuf,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+
+use crate::transactions::Transaction;
+
+use super::{GMAError, GMAFile, GMAParseLimits};
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum GMAVerifyStatus {
+	Ok,
+	CrcMismatch,
+	OutOfBounds,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GMAVerifyEntry {
+	pub path: String,
+	pub size: u64,
+	pub crc: u32,
+	pub computed_crc: Option<u32>,
+	pub status: GMAVerifyStatus,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum GMAChecksumStatus {
+	Ok,
+	Mismatch {
+		expected: u32,
+		computed: u32,
+	},
+	/// The file ends exactly where the last entry ends
+	Missing,
+	/// There are bytes after the last entry that aren't a 4 byte checksum
+	TrailingData {
+		bytes: u64,
+	},
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GMAVerifyReport {
+	/// Whether every entry is intact. The trailing checksum doesn't count towards this, as older versions of gmpublisher computed it over the header only.
+	pub ok: bool,
+	pub size: u64,
+	pub checksum: GMAChecksumStatus,
+	pub entries: Vec<GMAVerifyEntry>,
+}
+impl GMAVerifyReport {
+	pub fn failed(&self) -> impl Iterator<Item = &GMAVerifyEntry> {
+		self.entries.iter().filter(|entry| entry.status != GMAVerifyStatus::Ok)
+	}
+}
+
+/// Reads up to `bytes` bytes from `r`, feeding each chunk to `f`. Returns the number of bytes actually read.
+fn consume_bytes<R: Read + ?Sized, F: FnMut(&[u8])>(r: &mut R, bytes: u64, buf: &mut [u8], mut f: F) -> Result<u64, std::io::Error> {
+	let mut remaining = bytes;
+	while remaining > 0 {
+		let want = remaining.min(buf.len() as u64) as usize;
+		let read = match r.read(&mut buf[..want]) {
+			Ok(0) => break,
+			Ok(read) => read,
+			Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		};
+		f(&buf[..read]);
+		remaining -= read as u64;
+	}
+	Ok(bytes - remaining)
+}
+
+impl GMAFile {
+	/// Streams every entry of the GMA, recomputing its CRC32 and the trailing CRC32 of the whole file.
+	pub fn verify(&mut self, transaction: &Transaction) -> Result<GMAVerifyReport, GMAError> {
+		main_thread_forbidden!();
+
+		let result = (|| {
+			// Entries of truncated GMAs are reported as out of bounds rather than failing the parse
+			self.entries_with_limits(&GMAParseLimits {
+				allow_truncated: true,
+				..Default::default()
+			})?;
+
+			let mut handle = self.read()?;
+			let size = crate::stream_len(&mut *handle)?;
+			let entries_start = self.pointers.entries;
+			let entries = self.entries.as_ref().unwrap();
+
+			let size_f = size as f64;
+			let mut buf = vec![0u8; 64 * 1024];
+
+			let mut file_crc = crc32fast::Hasher::new();
+			let mut pos = 0;
+
+			handle.seek(SeekFrom::Start(0))?;
+
+			// Header, metadata and entries list
+			pos += consume_bytes(&mut *handle, entries_start, &mut buf, |chunk| file_crc.update(chunk))?;
+
+			let mut report = Vec::with_capacity(entries.len());
+			for entry in entries {
+				if transaction.aborted() {
+					return Err(GMAError::Cancelled);
+				}
+
+				let start = entry.offset;
+				if start > pos {
+					// Skip over any data belonging to entries we didn't index
+					pos += consume_bytes(&mut *handle, start - pos, &mut buf, |chunk| file_crc.update(chunk))?;
+				}
+
+				let in_bounds = start.checked_add(entry.size).map(|end| end <= size).unwrap_or(false);
+
+				let computed_crc = if in_bounds && pos == start {
+					let mut entry_crc = crc32fast::Hasher::new();
+					pos += consume_bytes(&mut *handle, entry.size, &mut buf, |chunk| {
+						file_crc.update(chunk);
+						entry_crc.update(chunk);
+					})?;
+					Some(entry_crc.finalize())
+				} else {
+					None
+				};
+
+				report.push(GMAVerifyEntry {
+					path: entry.path.clone(),
+					size: entry.size,
+					crc: entry.crc,
+					computed_crc,
+					status: match computed_crc {
+						None => GMAVerifyStatus::OutOfBounds,
+						Some(crc) if crc != entry.crc => GMAVerifyStatus::CrcMismatch,
+						Some(_) => GMAVerifyStatus::Ok,
+					},
+				});
+
+				transaction.progress(pos as f64 / size_f);
+			}
+
+			// Whatever's left of a truncated entry isn't trailing data
+			let entries_end = entries
+				.iter()
+				.map(|entry| entry.offset.saturating_add(entry.size))
+				.max()
+				.unwrap_or(entries_start);
+
+			let checksum = match size.saturating_sub(pos.max(entries_end)) {
+				0 => GMAChecksumStatus::Missing,
+				4 => {
+					let expected = handle.read_u32::<LittleEndian>()?;
+					let computed = file_crc.finalize();
+					if expected == computed {
+						GMAChecksumStatus::Ok
+					} else {
+						GMAChecksumStatus::Mismatch { expected, computed }
+					}
+				}
+				bytes => GMAChecksumStatus::TrailingData { bytes },
+			};
+
+			let ok = report.iter().all(|entry| entry.status == GMAVerifyStatus::Ok);
+
+			Ok(GMAVerifyReport {
+				ok,
+				size,
+				checksum,
+				entries: report,
+			})
+		})();
+
+		match result {
+			Ok(ref report) => transaction.finished(report.clone()),
+			Err(ref error) => {
+				if !transaction.aborted() {
+					transaction.error(error.to_string(), turbonone!());
+				}
+			}
+		}
+
+		result
+	}
+}
+
+#[tauri::command]
+pub fn verify_gma(gma_path: PathBuf) -> Option<u32> {
+	let mut gma = GMAFile::open(gma_path).ok()?;
+
+	let transaction = transaction!();
+	let id = transaction.id;
+
+	rayon::spawn(move || {
+		ignore! { gma.verify(&transaction) };
+	});
+
+	Some(id)
+}
+
+#[test]
+fn test_verify_truncated() {
+	let mut bytes = super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")]);
+	bytes.truncate(bytes.len() - 4 - 3);
+
+	assert!(matches!(
+		GMAFile::open_bytes(bytes.clone(), "truncated.gma").unwrap().entries(),
+		Err(GMAError::LimitExceeded {
+			limit: super::GMALimit::EntriesSize,
+			..
+		})
+	));
+
+	let report = GMAFile::open_bytes(bytes, "truncated.gma").unwrap().verify(&transaction!()).unwrap();
+	assert!(!report.ok);
+	assert_eq!(report.checksum, GMAChecksumStatus::Missing);
+	assert_eq!(
+		report.entries.iter().map(|entry| (entry.path.as_str(), entry.status)).collect::<Vec<_>>(),
+		[
+			("lua/autorun/a.lua", GMAVerifyStatus::Ok),
+			("lua/autorun/b.lua", GMAVerifyStatus::OutOfBounds)
+		]
+	);
+}
+
+#[test]
+fn test_verify() {
+	let bytes = super::test_gma(&[("lua/autorun/a.lua", b"print('a')"), ("lua/autorun/b.lua", b"print('b')")]);
+
+	let report = GMAFile::open_bytes(bytes.clone(), "good.gma").unwrap().verify(&transaction!()).unwrap();
+	assert!(report.ok);
+	assert_eq!(report.checksum, GMAChecksumStatus::Ok);
+	assert_eq!(report.size, bytes.len() as u64);
+	assert_eq!(report.failed().count(), 0);
+
+	// Older versions of gmpublisher wrote the wrong trailing checksum, which shouldn't fail intact entries
+	let mut old_checksum = bytes.clone();
+	let len = old_checksum.len();
+	old_checksum[len - 4..].copy_from_slice(&[0, 0, 0, 0]);
+	let report = GMAFile::open_bytes(old_checksum, "old.gma").unwrap().verify(&transaction!()).unwrap();
+	assert!(report.ok);
+	assert!(matches!(report.checksum, GMAChecksumStatus::Mismatch { expected: 0, .. }));
+
+	let mut corrupted = bytes;
+	let offset = corrupted.windows(10).position(|window| window == b"print('b')").unwrap();
+	corrupted[offset] = b'P';
+	let report = GMAFile::open_bytes(corrupted, "corrupted.gma").unwrap().verify(&transaction!()).unwrap();
+	assert!(!report.ok);
+	assert!(matches!(report.checksum, GMAChecksumStatus::Mismatch { .. }));
+	assert_eq!(
+		report.failed().map(|entry| (entry.path.as_str(), entry.status)).collect::<Vec<_>>(),
+		[("lua/autorun/b.lua", GMAVerifyStatus::CrcMismatch)]
+	);
+}
@@ -124,7 +124,8 @@ lazy_static! {
 }
 
 fn download_addon_whitelist() -> &'static [&'static str] {
-	if std::env::var_os("ADDON_WHITELIST_OFFLINE").is_some() {
+	// Tests run in parallel and shouldn't depend on the network, so they always use the built in list
+	if cfg!(test) || std::env::var_os("ADDON_WHITELIST_OFFLINE").is_some() {
 		return ADDON_WHITELIST_OFFLINE;
 	}
 
@@ -165,7 +166,8 @@ fn download_addon_whitelist() -> &'static [&'static str] {
 				));
 			}
 
-			println!("Downloaded up to date addon whitelist: {wildcard:#?}");
+			// Not stdout, the CLI can be writing a GMA to it
+			eprintln!("Downloaded up to date addon whitelist: {wildcard:#?}");
 
 			Ok(&*wildcard.leak())
 		})
@@ -1,190 +1,524 @@
 use byteorder::{LittleEndian, WriteBytesExt};
-use lazy_static::lazy_static;
-use rayon::ThreadPool;
 use std::{
-	collections::LinkedList,
-	fs::{self, File},
-	io::{BufWriter, Write},
-	path::Path,
-	sync::{atomic::AtomicBool, Arc},
+	fs::File,
+	io::{BufWriter, Read, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
 	time::SystemTime,
 };
 
 use path_slash::PathExt;
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
 use crate::{transactions::Transaction, GMAFile, NTStringWriter};
 
-use super::{whitelist, GMAError, GMAMetadata};
+use super::{whitelist, GMABuilder, GMAError, GMAHeader, GMAMetadata};
 
 use super::GMA_HEADER;
 
-lazy_static! {
-	static ref THREAD_POOL: ThreadPool = thread_pool!();
-}
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
 
 impl NTStringWriter for BufWriter<File> {}
 
-impl GMAFile {
-	pub fn write(&self) -> Result<BufWriter<File>, GMAError> {
-		Ok(BufWriter::new(File::create(&self.path)?))
+#[derive(Debug, Clone, Default)]
+pub struct GMACreateOptions {
+	/// Header timestamp to use instead of the current time. Falls back to `SOURCE_DATE_EPOCH` if set.
+	pub timestamp: Option<u64>,
+
+	/// Defaults the timestamp to 0 instead of the current time, and sorts and deduplicates the addon.json lists
+	pub deterministic: bool,
+}
+impl GMACreateOptions {
+	pub fn deterministic() -> Self {
+		Self {
+			timestamp: None,
+			deterministic: true,
+		}
 	}
 
-	pub fn create<P: AsRef<Path>>(&self, src_path: P, transaction: Transaction) -> Result<(), GMAError> {
-		let mut f = self.write()?;
+	/// Everything that comes before the entries list of a GMA with `metadata` and the header `fields`, using the timestamp of these options
+	pub(crate) fn header(&self, metadata: &GMAMetadata, fields: &GMAHeader) -> Result<Vec<u8>, std::io::Error> {
+		let description = match metadata {
+			GMAMetadata::Legacy { .. } => "Description".to_string(),
+			GMAMetadata::Standard { .. } => self.addon_json(metadata),
+		};
 
-		let src_path = src_path.as_ref();
+		write_header(
+			metadata.title(),
+			&description,
+			&GMAHeader {
+				timestamp: self.timestamp(),
+				..fields.clone()
+			},
+		)
+	}
 
-		let metadata = self.metadata.as_ref().expect("Expected metadata to be set");
-		let ignore = metadata.ignore().map(|ignore| {
-			ignore
-				.iter()
-				.map(|ignore| {
-					let mut ignore = ignore.to_owned();
-					ignore.push('\0');
-					ignore
-				})
-				.collect::<Vec<_>>()
-				.into_boxed_slice()
-		});
+	fn timestamp(&self) -> u64 {
+		self.timestamp_or_epoch(std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.trim().parse().ok()))
+	}
 
-		let (title, addon_json) = match metadata {
-			GMAMetadata::Legacy { title, .. } => (title.as_str(), None),
-			GMAMetadata::Standard { title, .. } => (title.as_str(), Some(metadata)),
-		};
+	fn timestamp_or_epoch(&self, source_date_epoch: Option<u64>) -> u64 {
+		self.timestamp.or(source_date_epoch).unwrap_or_else(|| {
+			if self.deterministic {
+				0
+			} else {
+				match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+					Ok(unix) => unix.as_secs(),
+					Err(_) => 0,
+				}
+			}
+		})
+	}
 
-		f.write_all(GMA_HEADER)?;
+	fn addon_json(&self, metadata: &GMAMetadata) -> String {
+		if !self.deterministic {
+			return serde_json::ser::to_string(metadata).unwrap();
+		}
 
-		f.write_u8(3)?; // gma version
+		let mut metadata = metadata.clone();
+		if let GMAMetadata::Standard { tags, ignore, .. } = &mut metadata {
+			tags.sort_unstable();
+			tags.dedup();
+			ignore.sort_unstable();
+			ignore.dedup();
+		}
+		serde_json::ser::to_string(&metadata).unwrap()
+	}
+}
 
-		// steamid [unused]
-		f.write_u64::<LittleEndian>(0)?;
+pub(crate) struct SourceEntry {
+	pub path: PathBuf,
+	pub relative_path: String,
+	pub size: u64,
+}
 
-		// timestamp [unused]
-		f.write_u64::<LittleEndian>(match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-			Ok(unix) => unix.as_secs(),
-			Err(_) => 0,
-		})?;
+/// Prepares the `ignore` globs of an addon.json for `source_path_allowed`
+pub(crate) fn ignore_globs(ignore: Option<&[String]>) -> Option<Vec<String>> {
+	ignore.map(|ignore| {
+		ignore
+			.iter()
+			.map(|ignore| {
+				let mut ignore = ignore.to_owned();
+				ignore.push('\0');
+				ignore
+			})
+			.collect::<Vec<_>>()
+	})
+}
 
-		// required content [unused]
-		f.write_u8(0)?;
+/// Whether a file at `relative_path` gets packed into a GMA. Files that aren't whitelisted are reported to the transaction.
+pub(crate) fn source_path_allowed(relative_path: &str, ignore: Option<&[String]>, transaction: &Transaction) -> bool {
+	if !whitelist::check(relative_path) {
+		transaction.data(("ERR_WHITELIST", relative_path.to_owned()));
+		return false;
+	}
 
-		// addon name
-		f.write_nt_string(title)?;
+	match ignore {
+		Some(ignore) => !whitelist::is_ignored(relative_path, ignore),
+		None => true,
+	}
+}
 
-		// addon description
-		match addon_json {
-			Some(addon_json) => f.write_nt_string(serde_json::ser::to_string(addon_json).as_deref().unwrap())?,
-			None => f.write_nt_string("Description")?,
-		};
+/// Every file in `src_path`, along with the path it would have inside a GMA
+fn source_files(src_path: &Path) -> impl Iterator<Item = (DirEntry, String)> {
+	let root_path_strip_len = src_path.to_string_lossy().len();
+
+	WalkDir::new(src_path)
+		.follow_links(true)
+		.into_iter()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_file())
+		.map(move |entry| {
+			let relative_path = entry.path().to_slash_lossy()[root_path_strip_len..].trim_matches('/').to_lowercase();
+			(entry, relative_path)
+		})
+}
 
-		// addon author [unused]
-		f.write_nt_string("Author Name")?;
-
-		// addon version [unused]
-		f.write_i32::<LittleEndian>(1)?;
-
-		// file list
-		let (error, rx, total) = {
-			let error = Arc::new(AtomicBool::new(false));
-
-			let (tx, rx) = crossbeam::channel::unbounded();
-
-			let root_path_strip_len = src_path.to_string_lossy().len();
-
-			let mut total = 0.;
-			for (path, relative_path) in WalkDir::new(src_path).follow_links(true).into_iter().filter_map(|entry| {
-				entry.ok().and_then(|entry| {
-					if entry.file_type().is_file() {
-						let path = entry.into_path();
-
-						let relative_path = path.to_slash_lossy()[root_path_strip_len..].trim_matches('/').to_lowercase();
-
-						if whitelist::check(&relative_path) {
-							if let Some(ref ignore) = ignore {
-								if whitelist::is_ignored(&relative_path, ignore) {
-									return None;
-								}
-							}
-							return Some((path, relative_path));
-						} else {
-							transaction.data(("ERR_WHITELIST", relative_path));
-						}
-					}
-					None
-				})
-			}) {
-				if error.load(std::sync::atomic::Ordering::Acquire) {
-					break;
-				}
+/// Walks `src_path` for the files that would be packed into a GMA, applying the whitelist and `ignore` globs
+pub(crate) fn source_entries(src_path: &Path, ignore: Option<&[String]>, transaction: &Transaction) -> Result<Vec<SourceEntry>, GMAError> {
+	let ignore = ignore_globs(ignore);
 
-				let tx = tx.clone();
-				let transaction = transaction.clone();
-				let error = error.clone();
-				THREAD_POOL.spawn(move || {
-					let contents = match fs::read(&path) {
-						Ok(contents) => contents,
-						Err(_) => {
-							return {
-								error.store(true, std::sync::atomic::Ordering::Release);
-								transaction.error("ERR_PATH_IO_ERROR", path);
-							}
-						}
-					};
-
-					let mut crc32 = crc32fast::Hasher::new();
-					crc32.reset();
-					crc32.update(&contents);
-					let crc32 = crc32.finalize();
-
-					tx.send((relative_path.into_bytes().into_boxed_slice(), contents.into_boxed_slice(), crc32))
-						.unwrap();
-				});
-
-				total += 1.;
-			}
+	let mut entries = Vec::new();
+	for (entry, relative_path) in source_files(src_path) {
+		if !source_path_allowed(&relative_path, ignore.as_deref(), transaction) {
+			continue;
+		}
 
-			(error, rx, total)
+		let size = match entry.metadata() {
+			Ok(metadata) => metadata.len(),
+			Err(error) => {
+				let error = GMAError::from(error.into_io_error().unwrap_or_else(|| std::io::ErrorKind::Other.into())).with_entry(relative_path);
+				transaction.error("ERR_PATH_IO_ERROR", entry.into_path());
+				return Err(error);
+			}
 		};
 
-		let mut entries_buf = LinkedList::new();
+		entries.push(SourceEntry {
+			path: entry.into_path(),
+			relative_path,
+			size,
+		});
+	}
+
+	// Don't depend on the order the filesystem gives us the files in
+	entries.sort_unstable_by(|a, b| a.relative_path.cmp(&b.relative_path));
 
-		let mut i = 0;
-		let mut i_f: f64 = 0.;
-		while let Ok((path, contents, crc32)) = rx.recv() {
-			i += 1;
+	Ok(entries)
+}
+
+/// Files in `src_path` that aren't ignored, by default or by `ignore`, but would be left out of a GMA because they aren't whitelisted, sorted
+pub fn whitelist_violations(src_path: &Path, ignore: Option<&[String]>) -> Vec<String> {
+	let ignore = ignore_globs(ignore);
+
+	let mut violations = source_files(src_path)
+		.map(|(_, relative_path)| relative_path)
+		.filter(|relative_path| whitelist::filter_default_ignored(relative_path))
+		.filter(|relative_path| {
+			!ignore
+				.as_deref()
+				.map(|ignore| whitelist::is_ignored(relative_path, ignore))
+				.unwrap_or(false)
+		})
+		.filter(|relative_path| !whitelist::check(relative_path))
+		.collect::<Vec<_>>();
+	violations.sort_unstable();
+	violations
+}
 
-			f.write_u32::<LittleEndian>(i as u32)?;
-			f.write_all(&path)?;
-			f.write_u8(0)?;
-			f.write_i64::<LittleEndian>(contents.len() as i64)?;
-			f.write_u32::<LittleEndian>(crc32)?;
+/// Writes everything that comes before the entries list: the magic, version, header fields and metadata of the addon
+pub(crate) fn write_header(title: &str, description: &str, fields: &GMAHeader) -> Result<Vec<u8>, std::io::Error> {
+	let mut header: Vec<u8> = Vec::new();
 
-			entries_buf.push_back(contents);
+	header.write_all(GMA_HEADER)?;
 
-			i_f += 1.;
-			transaction.progress(i_f / total);
-		}
+	header.write_u8(3)?; // gma version
+
+	header.write_u64::<LittleEndian>(fields.steamid)?;
+	header.write_u64::<LittleEndian>(fields.timestamp)?;
+
+	// required content, terminated by an empty string
+	for content in fields.required_content.iter() {
+		header.write_nt_string(content)?;
+	}
+	header.write_u8(0)?;
 
-		f.write_u32::<LittleEndian>(0)?;
+	// addon name
+	header.write_nt_string(title)?;
 
-		for contents in entries_buf.into_iter() {
-			f.write_all(&contents)?;
+	// addon description
+	header.write_nt_string(description)?;
+
+	header.write_nt_string(&fields.author)?;
+	header.write_i32::<LittleEndian>(fields.addon_version)?;
+
+	Ok(header)
+}
+
+/// Writes a GMA whose entry contents are streamed from any source.
+///
+/// The header and entries list are kept in memory and rewritten once every entry has been streamed and its CRC is known,
+/// unless the CRCs were given up front with `GMAWriter::new_streamed`.
+pub struct GMAWriter<W: Write> {
+	w: W,
+	header: Vec<u8>,
+	/// Offset of each entry's CRC in `header`, its size, and its CRC if it was given up front
+	entries: Vec<(usize, u64, Option<u32>)>,
+	next: usize,
+	data_crc32: crc32fast::Hasher,
+	buf: Box<[u8]>,
+}
+impl<W: Write + Seek> GMAWriter<W> {
+	/// `header` is everything that comes before the entries list, see `write_header`
+	pub fn new<I, P>(w: W, header: Vec<u8>, entries: I) -> Result<Self, std::io::Error>
+	where
+		I: IntoIterator<Item = (P, u64)>,
+		P: AsRef<[u8]>,
+	{
+		GMAWriter::with_entries(w, header, entries.into_iter().map(|(path, size)| (path, size, None)))
+	}
+
+	/// Fills in the CRCs of the entries and writes the trailing CRC of the GMA
+	pub fn finish(mut self) -> Result<W, std::io::Error> {
+		self.ensure_finished()?;
+
+		self.w.seek(SeekFrom::Start(0))?;
+		self.w.write_all(&self.header)?;
+		self.w.seek(SeekFrom::End(0))?;
+
+		self.write_trailer()
+	}
+}
+impl<W: Write> GMAWriter<W> {
+	/// Like `GMAWriter::new`, but for writers that can't seek, like stdout.
+	/// The CRC of every entry is known up front, so the entries list is written complete and everything after it is only appended.
+	pub fn new_streamed<I, P>(w: W, header: Vec<u8>, entries: I) -> Result<Self, std::io::Error>
+	where
+		I: IntoIterator<Item = (P, u64, u32)>,
+		P: AsRef<[u8]>,
+	{
+		GMAWriter::with_entries(w, header, entries.into_iter().map(|(path, size, crc)| (path, size, Some(crc))))
+	}
+
+	fn with_entries<I, P>(mut w: W, mut header: Vec<u8>, entries: I) -> Result<Self, std::io::Error>
+	where
+		I: IntoIterator<Item = (P, u64, Option<u32>)>,
+		P: AsRef<[u8]>,
+	{
+		let mut crc_offsets = Vec::new();
+		for (i, (path, size, crc)) in entries.into_iter().enumerate() {
+			header.write_u32::<LittleEndian>(i as u32 + 1)?;
+			header.write_all(path.as_ref())?;
+			header.write_u8(0)?;
+			header.write_i64::<LittleEndian>(size as i64)?;
+
+			crc_offsets.push((header.len(), size, crc));
+			header.write_u32::<LittleEndian>(crc.unwrap_or(0))?; // filled in once the contents have been streamed if it isn't known yet
 		}
 
-		let written = f.buffer();
+		header.write_u32::<LittleEndian>(0)?;
+
+		w.write_all(&header)?;
+
+		Ok(Self {
+			w,
+			header,
+			entries: crc_offsets,
+			next: 0,
+			data_crc32: crc32fast::Hasher::new(),
+			buf: vec![0u8; STREAM_BUFFER_SIZE].into_boxed_slice(),
+		})
+	}
+
+	/// Streams the contents of the next entry from `r`, which must provide exactly as many bytes as the size the entry was declared with
+	pub fn write_entry<R: Read + ?Sized>(&mut self, r: &mut R) -> Result<u32, std::io::Error> {
+		let (crc_offset, size, declared_crc) = *self
+			.entries
+			.get(self.next)
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "all entries have already been written"))?;
+
+		let mut r = r.take(size);
 
 		let mut crc32 = crc32fast::Hasher::new();
-		crc32.reset();
-		crc32.update(written);
+		let mut remaining = size;
+		while remaining > 0 {
+			let read = match r.read(&mut self.buf) {
+				Ok(0) => break,
+				Ok(read) => read,
+				Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+			};
+
+			let chunk = &self.buf[..read];
+			self.w.write_all(chunk)?;
+			crc32.update(chunk);
+			self.data_crc32.update(chunk);
+
+			remaining -= read as u64;
+		}
+
+		if remaining != 0 {
+			// The source shrunk since we wrote its size into the entries list
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+
+		// Or it grew, and what was written is cut off
+		let r = r.into_inner();
+		loop {
+			match r.read(&mut [0u8; 1]) {
+				Ok(0) => break,
+				Ok(_) => {
+					return Err(std::io::Error::new(
+						std::io::ErrorKind::InvalidData,
+						"source is larger than the size it was declared with",
+					))
+				}
+				Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+			}
+		}
+
 		let crc32 = crc32.finalize();
+		match declared_crc {
+			// The entries list has already been written with the CRC the source had when it was first read
+			Some(declared_crc) if declared_crc != crc32 => {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					"source changed since its CRC was computed",
+				))
+			}
+			Some(_) => {}
+			None => self.header[crc_offset..crc_offset + 4].copy_from_slice(&crc32.to_le_bytes()),
+		}
+		self.next += 1;
 
-		f.write_u32::<LittleEndian>(crc32)?;
+		Ok(crc32)
+	}
 
-		if Arc::try_unwrap(error).unwrap().into_inner() {
-			return Err(GMAError::IOError);
+	/// Writes the trailing CRC of a GMA created with `GMAWriter::new_streamed`, without seeking
+	pub fn finish_streamed(self) -> Result<W, std::io::Error> {
+		self.ensure_finished()?;
+		if self.entries.iter().any(|(_, _, crc)| crc.is_none()) {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not all CRCs were known up front"));
 		}
 
+		self.write_trailer()
+	}
+
+	fn ensure_finished(&self) -> Result<(), std::io::Error> {
+		if self.next != self.entries.len() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not all entries have been written"));
+		}
 		Ok(())
 	}
+
+	fn write_trailer(mut self) -> Result<W, std::io::Error> {
+		let mut crc32 = crc32fast::Hasher::new();
+		crc32.update(&self.header);
+		crc32.combine(&self.data_crc32);
+
+		self.w.write_u32::<LittleEndian>(crc32.finalize())?;
+		self.w.flush()?;
+
+		Ok(self.w)
+	}
+}
+
+impl GMAFile {
+	pub fn write(&self) -> Result<BufWriter<File>, GMAError> {
+		Ok(BufWriter::new(File::create(&self.path)?))
+	}
+
+	pub fn create<P: AsRef<Path>>(&self, src_path: P, transaction: Transaction) -> Result<(), GMAError> {
+		self.create_with_options(src_path, transaction, GMACreateOptions::default())
+	}
+
+	pub fn create_with_options<P: AsRef<Path>>(&self, src_path: P, transaction: Transaction, options: GMACreateOptions) -> Result<(), GMAError> {
+		let metadata = self.metadata.as_ref().expect("Expected metadata to be set");
+
+		// Only file metadata is read here, the contents are streamed from disk once the entries list has been written
+		let mut builder = GMABuilder::new(metadata.clone());
+		builder.header(self.header.clone().unwrap_or_default()).options(options);
+		builder.add_dir(src_path, &transaction)?;
+		builder.write_to(self.write()?, &transaction)?;
+
+		Ok(())
+	}
+}
+
+#[test]
+fn test_deterministic_create() {
+	let src_path = super::test_dir("deterministic");
+	std::fs::create_dir_all(src_path.join("lua/autorun")).unwrap();
+	std::fs::create_dir_all(src_path.join("materials/test")).unwrap();
+	std::fs::write(src_path.join("lua/autorun/test.lua"), "print('test')").unwrap();
+	std::fs::write(src_path.join("materials/test/test.vmt"), "\"VertexLitGeneric\" {}").unwrap();
+
+	let pack = |tags: &[&str], timestamp: u64| {
+		let mut builder = GMABuilder::new(GMAMetadata::Standard {
+			title: "Test".to_string(),
+			addon_type: "tool".to_string(),
+			tags: tags.iter().map(|tag| tag.to_string()).collect(),
+			ignore: vec![],
+		});
+		builder.options(GMACreateOptions {
+			timestamp: Some(timestamp),
+			deterministic: true,
+		});
+		builder.add_dir(&src_path, &transaction!()).unwrap();
+		builder.write_to(std::io::Cursor::new(Vec::new()), &transaction!()).unwrap().into_inner()
+	};
+
+	let first = pack(&["fun", "build"], 1000);
+	let second = pack(&["build", "fun", "fun"], 1000);
+	let later = pack(&["fun", "build"], 2000);
+
+	std::fs::remove_dir_all(&src_path).ok();
+
+	// The order and duplicates of the tags don't matter, only the timestamp does
+	assert_eq!(first, second);
+	assert_ne!(first, later);
+	assert_eq!(first.len(), later.len());
+}
+
+#[test]
+fn test_write_entry_short_source() {
+	let mut w = GMAWriter::new(std::io::Cursor::new(Vec::new()), Vec::new(), [("lua/autorun/test.lua", 10)]).unwrap();
+	let error = w.write_entry(&mut &b"short"[..]).unwrap_err();
+	assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+	assert!(w.finish().is_err());
+}
+
+#[test]
+fn test_write_entry_long_source() {
+	let mut w = GMAWriter::new(std::io::Cursor::new(Vec::new()), Vec::new(), [("lua/autorun/test.lua", 5)]).unwrap();
+	let error = w.write_entry(&mut &b"longer than declared"[..]).unwrap_err();
+	assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+	assert!(w.finish().is_err());
+}
+
+#[test]
+fn test_timestamp() {
+	let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+
+	assert_eq!(GMACreateOptions::deterministic().timestamp_or_epoch(None), 0);
+	assert_eq!(GMACreateOptions::deterministic().timestamp_or_epoch(Some(1234)), 1234);
+	assert!(GMACreateOptions::default().timestamp_or_epoch(None) >= now);
+	assert_eq!(GMACreateOptions::default().timestamp_or_epoch(Some(1234)), 1234);
+
+	// An explicit timestamp wins over SOURCE_DATE_EPOCH
+	let options = GMACreateOptions {
+		timestamp: Some(42),
+		deterministic: true,
+	};
+	assert_eq!(options.timestamp_or_epoch(Some(1234)), 42);
+}
+
+#[test]
+fn test_header_roundtrip() {
+	let metadata = GMAMetadata::Standard {
+		title: "Test".to_string(),
+		addon_type: "tool".to_string(),
+		tags: vec!["fun".to_string(), "build".to_string()],
+		ignore: vec!["*.psd".to_string()],
+	};
+	let header = GMAHeader {
+		steamid: 76561197960287930,
+		timestamp: 0,
+		required_content: vec!["123456".to_string(), "789".to_string()],
+		author: "Someone".to_string(),
+		addon_version: 3,
+	};
+
+	let mut builder = GMABuilder::new(metadata);
+	builder.header(header.clone()).options(GMACreateOptions {
+		timestamp: Some(1600000000),
+		deterministic: false,
+	});
+	builder.add_bytes("lua/autorun/test.lua", b"print('test')".to_vec()).unwrap();
+	let bytes = builder.write_to(std::io::Cursor::new(Vec::new()), &transaction!()).unwrap().into_inner();
+
+	let mut gma = GMAFile::open_bytes(bytes, "header.gma").unwrap();
+	gma.metadata().unwrap();
+
+	assert_eq!(gma.version, 3);
+	assert_eq!(
+		gma.header,
+		Some(GMAHeader {
+			timestamp: 1600000000,
+			..header
+		})
+	);
+	match gma.metadata.as_ref().unwrap() {
+		GMAMetadata::Standard {
+			title,
+			addon_type,
+			tags,
+			ignore,
+		} => {
+			assert_eq!(title, "Test");
+			assert_eq!(addon_type, "tool");
+			assert_eq!(tags, &["fun", "build"]);
+			assert_eq!(ignore, &["*.psd"]);
+		}
+		GMAMetadata::Legacy { .. } => panic!("Expected the addon.json to be read back"),
+	}
 }
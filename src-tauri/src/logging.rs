@@ -57,7 +57,12 @@ lazy_static! {
 macro_rules! println {
 	($($arg:tt)*) => {
 		let log = format!($($arg)*);
-		std::println!("{}", &log);
+		// In the CLI, stdout is reserved for output that might be piped somewhere
+		if *crate::cli::CLI_MODE {
+			std::eprintln!("{}", &log);
+		} else {
+			std::println!("{}", &log);
+		}
 		crate::ignore! { crate::logging::LOG_CHANNEL.send(crate::logging::LogMessage::Stdout(log)) };
 	};
 }
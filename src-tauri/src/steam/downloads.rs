@@ -86,6 +86,7 @@ impl Downloads {
 
 			webview_emit!("ExtractionStarted", (transaction.id, turbonone!(), turbonone!(), Some(item)));
 
+			let mut decompressed = false;
 			let mut gma = if folder.is_dir() {
 				let mut gma_path = None;
 
@@ -120,6 +121,7 @@ impl Downloads {
 						match GMAFile::decompress(folder, transaction.clone()) {
 							Ok(gma) => {
 								transaction.progress_reset();
+								decompressed = true;
 								gma
 							}
 							Err(err) => return transaction.error(err.to_string(), turbonone!()),
@@ -138,6 +140,11 @@ impl Downloads {
 			if let Err(err) = gma.extract(extract_destination, &transaction, false, true) {
 				transaction.error(err.to_string(), turbonone!());
 			}
+
+			if decompressed {
+				// The decompressed GMA was only needed for extracting
+				std::fs::remove_file(&gma.path).ok();
+			}
 		});
 	}
 
@@ -0,0 +1,312 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use steamworks::PublishedFileId;
+
+use crate::gma::{whitelist_violations, GMACreateOptions, GMAFile, GMAHeader, GMAMetadata};
+
+use super::publishing::{ContentPath, PublishError, WorkshopIcon, WorkshopUpdateType, WORKSHOP_DEFAULT_ICON};
+
+/// Stage of an item update, as reported by Steam while it's being submitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkshopUploadStatus {
+	PreparingConfig,
+	PreparingContent,
+	UploadingContent,
+	UploadingPreviewFile,
+	CommittingChanges,
+}
+impl WorkshopUploadStatus {
+	pub fn key(&self) -> &'static str {
+		match self {
+			WorkshopUploadStatus::PreparingConfig => "PUBLISH_PREPARING_CONFIG",
+			WorkshopUploadStatus::PreparingContent => "PUBLISH_PREPARING_CONTENT",
+			WorkshopUploadStatus::UploadingContent => "PUBLISH_UPLOADING_CONTENT",
+			WorkshopUploadStatus::UploadingPreviewFile => "PUBLISH_UPLOADING_PREVIEW_FILE",
+			WorkshopUploadStatus::CommittingChanges => "PUBLISH_COMMITTING_CHANGES",
+		}
+	}
+}
+
+/// The Workshop calls publishing goes through, so that it can run against something other than Steam
+pub trait WorkshopPublisher {
+	/// SteamID64 and name of the user that's publishing, for the header of the GMA
+	fn author(&self) -> (u64, String);
+
+	/// Creates a new, empty Workshop item
+	fn create_item(&self) -> Result<PublishedFileId, PublishError>;
+
+	/// Submits `details` to the Workshop item `id`, calling `progress` with the status of the upload and how many of its bytes are done.
+	///
+	/// Returns whether the user needs to accept the Workshop legal agreement before the item can be seen by anyone else.
+	fn update_item(
+		&self,
+		id: PublishedFileId,
+		details: WorkshopUpdateType,
+		progress: &mut dyn FnMut(WorkshopUploadStatus, u64, u64),
+	) -> Result<bool, PublishError>;
+
+	fn delete_item(&self, id: PublishedFileId);
+}
+
+pub enum PublishProgress {
+	Packing,
+	CreatingItem,
+	Uploading { status: WorkshopUploadStatus, done: u64, total: u64 },
+}
+
+pub struct HeadlessPublish {
+	/// The addon folder, whose addon.json gives the title, type, tags and ignore globs
+	pub content_path: PathBuf,
+	/// The Workshop item to update, or `None` to create a new one
+	pub update_id: Option<PublishedFileId>,
+	/// Defaults to the gmpublisher icon for new items, and leaves the icon alone for updates
+	pub icon_path: Option<PathBuf>,
+	pub changes: Option<String>,
+	/// Packs the GMA with `GMACreateOptions::deterministic`
+	pub deterministic: bool,
+	/// Where the GMA is packed before it's uploaded. It's deleted afterwards.
+	pub staging_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadlessPublishOutcome {
+	pub id: PublishedFileId,
+	pub created: bool,
+	/// The item stays hidden until the user accepts the Workshop legal agreement
+	pub legal_agreement_required: bool,
+}
+
+/// Packs and publishes an addon folder without the GUI: no settings, no browser and no transactions.
+///
+/// New items are deleted again if their first upload fails.
+pub fn publish_headless<P: WorkshopPublisher + ?Sized>(
+	publisher: &P,
+	request: HeadlessPublish,
+	progress: &mut dyn FnMut(PublishProgress),
+) -> Result<HeadlessPublishOutcome, PublishError> {
+	let result = stage_and_publish(publisher, &request, progress);
+	std::fs::remove_dir_all(&request.staging_dir).ok();
+	result
+}
+
+fn stage_and_publish<P: WorkshopPublisher + ?Sized>(
+	publisher: &P,
+	request: &HeadlessPublish,
+	progress: &mut dyn FnMut(PublishProgress),
+) -> Result<HeadlessPublishOutcome, PublishError> {
+	if !request.content_path.is_dir() {
+		return Err(PublishError::InvalidContentPath);
+	}
+
+	let metadata = GMAMetadata::read_addon_json(&request.content_path).ok_or(PublishError::AddonJsonMissing)?;
+	let (title, addon_type, tags, ignore) = match &metadata {
+		GMAMetadata::Standard {
+			title,
+			addon_type,
+			tags,
+			ignore,
+		} => (title.clone(), addon_type.clone(), tags.clone(), ignore.as_slice()),
+		GMAMetadata::Legacy { .. } => return Err(PublishError::AddonJsonMissing),
+	};
+
+	let violations = whitelist_violations(&request.content_path, Some(ignore));
+	if !violations.is_empty() {
+		return Err(PublishError::NotWhitelisted(violations));
+	}
+
+	// Steam uploads everything in the content folder, so the icon has to live outside of it
+	let content_dir = request.staging_dir.join("content");
+	std::fs::create_dir_all(&content_dir)?;
+
+	let preview = match &request.icon_path {
+		Some(icon_path) => Some(WorkshopIcon::new(icon_path, false)?),
+		None if request.update_id.is_none() => Some(default_icon(&request.staging_dir)?),
+		None => None,
+	};
+
+	progress(PublishProgress::Packing);
+
+	let (steamid, author) = publisher.author();
+	let mut gma = GMAFile::new(content_dir.join("gmpublisher.gma"), Some(metadata.clone()));
+	gma.header = Some(GMAHeader {
+		steamid,
+		author,
+		..Default::default()
+	});
+	let options = GMACreateOptions {
+		deterministic: request.deterministic,
+		..Default::default()
+	};
+	gma.create_with_options(&request.content_path, transaction!(), options)?;
+
+	let content_path = ContentPath::new(content_dir)?;
+
+	let (id, created) = match request.update_id {
+		Some(id) => (id, false),
+		None => {
+			progress(PublishProgress::CreatingItem);
+			(publisher.create_item()?, true)
+		}
+	};
+
+	let details = match preview {
+		Some(preview) if created => WorkshopUpdateType::Creation {
+			title,
+			path: content_path,
+			tags,
+			addon_type,
+			preview,
+		},
+		_ => WorkshopUpdateType::Update {
+			title,
+			path: content_path,
+			tags,
+			addon_type,
+			preview,
+			changes: request.changes.clone(),
+		},
+	};
+
+	let result = publisher.update_item(id, details, &mut |status, done, total| {
+		progress(PublishProgress::Uploading { status, done, total });
+	});
+
+	match result {
+		Ok(legal_agreement_required) => Ok(HeadlessPublishOutcome {
+			id,
+			created,
+			legal_agreement_required,
+		}),
+		Err(error) => {
+			if created {
+				publisher.delete_item(id);
+			}
+			Err(error)
+		}
+	}
+}
+
+fn default_icon(staging_dir: &Path) -> Result<WorkshopIcon, PublishError> {
+	let path = staging_dir.join("gmpublisher_default_icon.png");
+	std::fs::write(&path, WORKSHOP_DEFAULT_ICON)?;
+	WorkshopIcon::new(path, false)
+}
+
+#[cfg(test)]
+struct LocalWorkshop {
+	legal_agreement_required: bool,
+	fail_upload: bool,
+	created: std::cell::RefCell<Vec<PublishedFileId>>,
+	deleted: std::cell::RefCell<Vec<PublishedFileId>>,
+	uploaded: std::cell::RefCell<Vec<(PublishedFileId, String, Vec<String>)>>,
+}
+#[cfg(test)]
+impl WorkshopPublisher for LocalWorkshop {
+	fn author(&self) -> (u64, String) {
+		(76561197960287930, "Local".to_string())
+	}
+
+	fn create_item(&self) -> Result<PublishedFileId, PublishError> {
+		let id = PublishedFileId(1000 + self.created.borrow().len() as u64);
+		self.created.borrow_mut().push(id);
+		Ok(id)
+	}
+
+	fn update_item(
+		&self,
+		id: PublishedFileId,
+		details: WorkshopUpdateType,
+		progress: &mut dyn FnMut(WorkshopUploadStatus, u64, u64),
+	) -> Result<bool, PublishError> {
+		let (title, path) = match details {
+			WorkshopUpdateType::Creation { title, path, .. } => (title, path),
+			WorkshopUpdateType::Update { title, path, .. } => (title, path),
+		};
+
+		let mut gma = GMAFile::open(&*path).unwrap();
+		gma.entries().unwrap();
+		let entries = gma.entries.as_ref().unwrap().iter().map(|entry| entry.path.clone()).collect();
+
+		progress(WorkshopUploadStatus::UploadingContent, 0, gma.size);
+		if self.fail_upload {
+			return Err(PublishError::IOError);
+		}
+		progress(WorkshopUploadStatus::UploadingContent, gma.size, gma.size);
+
+		self.uploaded.borrow_mut().push((id, title, entries));
+		Ok(self.legal_agreement_required)
+	}
+
+	fn delete_item(&self, id: PublishedFileId) {
+		self.deleted.borrow_mut().push(id);
+	}
+}
+
+#[test]
+fn test_publish_headless() {
+	let root = crate::gma::test_dir("publish_headless");
+	let content_path = root.join("addon");
+	std::fs::create_dir_all(content_path.join("lua/autorun")).unwrap();
+	std::fs::write(content_path.join("lua/autorun/test.lua"), "print('test')").unwrap();
+	std::fs::write(content_path.join("notes.psd"), "ignored").unwrap();
+	std::fs::write(
+		content_path.join("addon.json"),
+		r#"{"title": "Test", "type": "tool", "tags": ["fun"], "ignore": ["*.psd"]}"#,
+	)
+	.unwrap();
+
+	let request = |update_id: Option<PublishedFileId>| HeadlessPublish {
+		content_path: content_path.clone(),
+		update_id,
+		icon_path: None,
+		changes: None,
+		deterministic: true,
+		staging_dir: root.join("staging"),
+	};
+
+	let workshop = LocalWorkshop {
+		legal_agreement_required: true,
+		fail_upload: false,
+		created: Default::default(),
+		deleted: Default::default(),
+		uploaded: Default::default(),
+	};
+
+	let mut uploading = 0;
+	let outcome = publish_headless(&workshop, request(None), &mut |progress| {
+		if let PublishProgress::Uploading { .. } = progress {
+			uploading += 1;
+		}
+	})
+	.unwrap();
+	assert!(outcome.created);
+	assert!(outcome.legal_agreement_required);
+	assert_eq!(uploading, 2);
+	assert_eq!(
+		workshop.uploaded.borrow()[0],
+		(outcome.id, "Test".to_string(), vec!["lua/autorun/test.lua".to_string()])
+	);
+	assert!(!root.join("staging").exists());
+
+	let updated = publish_headless(&workshop, request(Some(outcome.id)), &mut |_| {}).unwrap();
+	assert!(!updated.created);
+	assert_eq!(updated.id, outcome.id);
+	assert_eq!(workshop.created.borrow().len(), 1);
+
+	let failing = LocalWorkshop {
+		fail_upload: true,
+		..workshop
+	};
+	assert!(publish_headless(&failing, request(None), &mut |_| {}).is_err());
+	assert_eq!(*failing.deleted.borrow(), vec![failing.created.borrow()[1]]);
+
+	std::fs::write(content_path.join("virus.exe"), "bad").unwrap();
+	match publish_headless(&failing, request(None), &mut |_| {}) {
+		Err(PublishError::NotWhitelisted(violations)) => assert_eq!(violations, vec!["virus.exe".to_string()]),
+		_ => panic!("Expected the whitelist to fail"),
+	}
+	assert_eq!(failing.created.borrow().len(), 2);
+
+	std::fs::remove_dir_all(root).ok();
+}
@@ -21,6 +21,7 @@ use crate::{
 use crate::webview_emit;
 
 pub mod downloads;
+pub mod headless;
 pub mod publishing;
 pub mod subscriptions;
 pub mod users;
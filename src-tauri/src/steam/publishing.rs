@@ -1,5 +1,5 @@
 use crate::{
-	gma::{GMAEntry, GMAFile, GMAFilePointers, GMAMetadata},
+	gma::{GMACreateOptions, GMAEntry, GMAError, GMAFile, GMAHeader, GMAMetadata},
 	Transaction, GMOD_APP_ID,
 };
 use image::{DynamicImage, GenericImageView, ImageError, ImageFormat};
@@ -18,6 +18,7 @@ use walkdir::WalkDir;
 #[cfg(not(target_os = "windows"))]
 use std::collections::HashSet;
 
+#[derive(Debug)]
 pub enum PublishError {
 	NotWhitelisted(Vec<String>),
 	NoEntries,
@@ -30,6 +31,8 @@ pub enum PublishError {
 	IOError,
 	SteamError(SteamError),
 	ImageError(ImageError),
+	AddonJsonMissing,
+	GMAError(GMAError),
 }
 impl std::fmt::Display for PublishError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -45,6 +48,8 @@ impl std::fmt::Display for PublishError {
 			PublishError::IOError => write!(f, "ERR_IO_ERROR"),
 			PublishError::SteamError(error) => write!(f, "ERR_STEAM_ERROR:{}", error),
 			PublishError::ImageError(error) => write!(f, "ERR_IMAGE_ERROR:{}", error),
+			PublishError::AddonJsonMissing => write!(f, "ERR_ADDON_JSON_MISSING"),
+			PublishError::GMAError(error) => write!(f, "{}", error),
 		}
 	}
 }
@@ -66,13 +71,21 @@ impl From<ImageError> for PublishError {
 		PublishError::ImageError(error)
 	}
 }
+impl From<GMAError> for PublishError {
+	fn from(error: GMAError) -> PublishError {
+		PublishError::GMAError(error)
+	}
+}
 impl From<std::io::Error> for PublishError {
 	fn from(_: std::io::Error) -> PublishError {
 		PublishError::IOError
 	}
 }
 
-use super::Steam;
+use super::{
+	headless::{WorkshopPublisher, WorkshopUploadStatus},
+	Steam,
+};
 pub struct ContentPath(PathBuf);
 impl std::ops::Deref for ContentPath {
 	type Target = PathBuf;
@@ -121,7 +134,7 @@ impl ContentPath {
 
 const WORKSHOP_ICON_MAX_SIZE: u64 = 1048576;
 const WORKSHOP_ICON_MIN_SIZE: u64 = 16;
-const WORKSHOP_DEFAULT_ICON: &[u8] = include_bytes!("../../../public/img/gmpublisher_default_icon.png");
+pub(crate) const WORKSHOP_DEFAULT_ICON: &[u8] = include_bytes!("../../../public/img/gmpublisher_default_icon.png");
 
 pub enum WorkshopIcon {
 	Custom {
@@ -229,8 +242,55 @@ pub enum WorkshopUpdateType {
 	},
 }
 
-impl Steam {
-	pub fn update(&self, id: PublishedFileId, details: WorkshopUpdateType, transaction: &Transaction) -> Result<bool, PublishError> {
+impl WorkshopUploadStatus {
+	fn from_steam(status: steamworks::UpdateStatus) -> Option<WorkshopUploadStatus> {
+		Some(match status {
+			steamworks::UpdateStatus::Invalid => return None,
+			steamworks::UpdateStatus::PreparingConfig => WorkshopUploadStatus::PreparingConfig,
+			steamworks::UpdateStatus::PreparingContent => WorkshopUploadStatus::PreparingContent,
+			steamworks::UpdateStatus::UploadingContent => WorkshopUploadStatus::UploadingContent,
+			steamworks::UpdateStatus::UploadingPreviewFile => WorkshopUploadStatus::UploadingPreviewFile,
+			steamworks::UpdateStatus::CommittingChanges => WorkshopUploadStatus::CommittingChanges,
+		})
+	}
+}
+
+impl WorkshopPublisher for Steam {
+	fn author(&self) -> (u64, String) {
+		let client = self.client();
+		(client.steam_id.raw(), client.friends().name())
+	}
+
+	fn create_item(&self) -> Result<PublishedFileId, PublishError> {
+		let published = Arc::new(Mutex::new(None));
+		let published_ref = published.clone();
+		self.client()
+			.ugc()
+			.create_item(GMOD_APP_ID, steamworks::FileType::Community, move |result| {
+				*published_ref.lock() = Some(result);
+			});
+
+		loop {
+			if let Some(published_ref) = published.try_lock() {
+				if published_ref.is_some() {
+					break;
+				}
+			}
+			self.run_callbacks();
+		}
+
+		match Arc::try_unwrap(published).unwrap().into_inner().unwrap() {
+			Ok((id, _)) => Ok(id),
+			Err(error) => Err(PublishError::SteamError(error)),
+		}
+	}
+
+	fn update_item(
+		&self,
+		id: PublishedFileId,
+		details: WorkshopUpdateType,
+		progress: &mut dyn FnMut(WorkshopUploadStatus, u64, u64),
+	) -> Result<bool, PublishError> {
 		use WorkshopUpdateType::*;
 
 		let result = Arc::new(Mutex::new(None));
@@ -289,25 +349,10 @@ impl Steam {
 			}
 		};
 
-		let mut last_processed;
 		let result = loop {
-			let (processed, progress, total) = update_handle.progress();
-			last_processed = processed;
-			if !matches!(processed, steamworks::UpdateStatus::Invalid) {
-				transaction.status(match processed {
-					steamworks::UpdateStatus::Invalid => unreachable!(),
-					steamworks::UpdateStatus::PreparingConfig => "PUBLISH_PREPARING_CONFIG",
-					steamworks::UpdateStatus::PreparingContent => "PUBLISH_PREPARING_CONTENT",
-					steamworks::UpdateStatus::UploadingContent => "PUBLISH_UPLOADING_CONTENT",
-					steamworks::UpdateStatus::UploadingPreviewFile => "PUBLISH_UPLOADING_PREVIEW_FILE",
-					steamworks::UpdateStatus::CommittingChanges => "PUBLISH_COMMITTING_CHANGES",
-				});
-			}
-			if total == 0 || last_processed != processed {
-				transaction.progress_reset();
-			} else {
-				transaction.data(total);
-				transaction.progress(progress as f64 / total as f64);
+			let (processed, done, total) = update_handle.progress();
+			if let Some(status) = WorkshopUploadStatus::from_steam(processed) {
+				progress(status, done, total);
 			}
 
 			if !result.is_locked() && result.lock().is_some() {
@@ -318,37 +363,38 @@ impl Steam {
 		};
 
 		match result {
-			Ok((_, legal_agreement)) => {
-				transaction.progress(1.);
-				Ok(legal_agreement)
-			}
+			Ok((_, legal_agreement)) => Ok(legal_agreement),
 			Err(error) => Err(PublishError::SteamError(error)),
 		}
 	}
 
-	pub fn publish(&self, details: WorkshopUpdateType, transaction: &Transaction) -> (Option<PublishedFileId>, Result<bool, PublishError>) {
-		debug_assert!(matches!(details, WorkshopUpdateType::Creation { .. }));
-
-		let published = Arc::new(Mutex::new(None));
-		let published_ref = published.clone();
-		self.client()
-			.ugc()
-			.create_item(GMOD_APP_ID, steamworks::FileType::Community, move |result| {
-				*published_ref.lock() = Some(result);
-			});
+	fn delete_item(&self, id: PublishedFileId) {
+		self.client().ugc().delete_item(id, |_| {});
+	}
+}
 
-		loop {
-			if let Some(published_ref) = published.try_lock() {
-				if published_ref.is_some() {
-					break;
-				}
+impl Steam {
+	pub fn update(&self, id: PublishedFileId, details: WorkshopUpdateType, transaction: &Transaction) -> Result<bool, PublishError> {
+		let legal_agreement = self.update_item(id, details, &mut |status, progress, total| {
+			transaction.status(status.key());
+			if total == 0 {
+				transaction.progress_reset();
+			} else {
+				transaction.data(total);
+				transaction.progress(progress as f64 / total as f64);
 			}
-			self.run_callbacks();
-		}
+		})?;
+
+		transaction.progress(1.);
+		Ok(legal_agreement)
+	}
+
+	pub fn publish(&self, details: WorkshopUpdateType, transaction: &Transaction) -> (Option<PublishedFileId>, Result<bool, PublishError>) {
+		debug_assert!(matches!(details, WorkshopUpdateType::Creation { .. }));
 
-		let id = match Arc::try_unwrap(published).unwrap().into_inner().unwrap() {
-			Ok((id, _)) => id,
-			Err(error) => return (None, Err(PublishError::SteamError(error))),
+		let id = match self.create_item() {
+			Ok(id) => id,
+			Err(error) => return (None, Err(error)),
 		};
 
 		(Some(id), self.update(id, details, transaction))
@@ -483,7 +529,9 @@ pub fn verify_whitelist(path: PathBuf) -> Result<(Vec<GMAEntry>, u64), PublishEr
 				path: relative_path,
 				size: entry_size,
 				crc: 0,
-				index: 0,
+				offset: 0,
+				duplicate: false,
+				raw_path: None,
 			});
 		}
 	}
@@ -591,31 +639,42 @@ pub fn publish(
 		path.push("gmpublisher.gma");
 
 		{
-			let gma = GMAFile {
-				// TODO convert to GMAFile::new()
-				path: path.clone(),
-				size: 0,
-				id: None,
-				metadata: Some(GMAMetadata::Standard {
+			let mut gma = GMAFile::new(
+				path.clone(),
+				Some(GMAMetadata::Standard {
 					title: title.clone(),
 					addon_type: addon_type.clone(),
 					tags: tags.clone(),
 					ignore: app_data!().settings.read().ignore_globs.clone(),
 				}),
-				entries: None,
-				pointers: GMAFilePointers::default(),
-				version: 3,
-				extracted_name: String::new(),
-				modified: None,
-				membuffer: None,
-			};
+			);
+			gma.header = Some(GMAHeader {
+				steamid: steam!().client().steam_id.raw(),
+				author: steam!().client().friends().name(),
+				..Default::default()
+			});
 
-			if let Err(error) = gma.create(&content_path_src, transaction.clone()) {
+			let options = GMACreateOptions {
+				deterministic: app_data!().settings.read().deterministic_packing,
+				..Default::default()
+			};
+			if let Err(error) = gma.create_with_options(&content_path_src, transaction.clone(), options) {
 				if !transaction.aborted() {
 					transaction.error(error.to_string(), turbonone!());
 				}
 				return;
 			}
+
+			// Let the user know how big the download players will see is before it goes up
+			if app_data!().settings.read().measure_download_size {
+				transaction.status("PUBLISH_COMPRESSING");
+				transaction.progress_reset();
+				match gma.compressed_size(&transaction) {
+					Ok(compressed_size) => transaction.data(("PUBLISH_COMPRESSED_SIZE", compressed_size)),
+					Err(_) if transaction.aborted() => return,
+					Err(error) => eprintln!("Failed to measure compressed GMA size: {error}"),
+				}
+			}
 		}
 
 		let mut content_path = path.clone();
@@ -175,7 +175,7 @@ impl Drop for TransactionInner {
 			self.abort();
 
 			#[cfg(debug_assertions)]
-			println!("{:#?}", backtrace::Backtrace::new());
+			eprintln!("{:#?}", backtrace::Backtrace::new());
 		}
 	}
 }
@@ -80,7 +80,7 @@ macro_rules! try_block {
 }
 
 lazy_static! {
-	pub static ref NUM_THREADS: usize = usize::max(num_cpus::get() - 2, 2);
+	pub static ref NUM_THREADS: usize = usize::max(num_cpus::get().saturating_sub(2), 2);
 }
 #[macro_export]
 macro_rules! thread_pool {
@@ -1,5 +1,5 @@
 use std::{
-	io::{BufRead, BufWriter, ErrorKind, Seek, SeekFrom, Write},
+	io::{BufRead, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
 	sync::Arc,
 };
 
@@ -81,24 +81,45 @@ pub fn stream_bytes_with_transaction<R: BufRead + ?Sized, W: Write>(
 	})
 }
 
+/// Decodes the bytes of a null terminated string, guessing the encoding if they aren't UTF-8
+pub fn decode_nt_string(nt_string: &[u8]) -> String {
+	match std::str::from_utf8(nt_string) {
+		Ok(str) => str.to_owned(),
+		Err(_) => {
+			// Some file paths aren't UTF-8 encoded, usually due to Windows NTFS
+			// This will simply guess the text encoding and decode it with that instead
+			let mut decoder = chardetng::EncodingDetector::new();
+			decoder.feed(nt_string, true);
+			let encoding = decoder.guess(None, false);
+			let (str, _, _) = encoding.decode(nt_string);
+			str.to_string()
+		}
+	}
+}
+
 pub trait NTStringReader: BufRead + Seek {
 	fn read_nt_string(&mut self) -> Result<String, std::io::Error> {
+		self.read_nt_string_limited(usize::MAX)
+	}
+
+	/// Reads a null terminated string of at most `max_len` bytes, failing with `ErrorKind::InvalidData` if it's any longer
+	fn read_nt_string_limited(&mut self, max_len: usize) -> Result<String, std::io::Error> {
+		Ok(decode_nt_string(&self.read_nt_bytes_limited(max_len)?))
+	}
+
+	/// Like `read_nt_string_limited`, but returns the raw bytes of the string without the null terminator
+	fn read_nt_bytes_limited(&mut self, max_len: usize) -> Result<Vec<u8>, std::io::Error> {
 		let mut buf = vec![];
-		let bytes_read = self.read_until(0, &mut buf)?;
-		let nt_string = &buf[0..bytes_read - 1];
-
-		Ok(match std::str::from_utf8(nt_string) {
-			Ok(str) => str.to_owned(),
-			Err(_) => {
-				// Some file paths aren't UTF-8 encoded, usually due to Windows NTFS
-				// This will simply guess the text encoding and decode it with that instead
-				let mut decoder = chardetng::EncodingDetector::new();
-				decoder.feed(nt_string, true);
-				let encoding = decoder.guess(None, false);
-				let (str, _, _) = encoding.decode(nt_string);
-				str.to_string()
-			}
-		})
+		let read = <&mut Self as Read>::take(self, (max_len as u64).saturating_add(1)).read_until(0, &mut buf)?;
+		if buf.pop() != Some(0) {
+			return Err(if read > max_len {
+				std::io::ErrorKind::InvalidData.into()
+			} else {
+				// Ran out of bytes before the null terminator
+				std::io::ErrorKind::UnexpectedEof.into()
+			});
+		}
+		Ok(buf)
 	}
 
 	fn skip_nt_string(&mut self) -> Result<usize, std::io::Error> {
@@ -39,6 +39,11 @@ impl WrappedWebview {
 	}
 
 	pub fn emit<D: Serialize + Send + 'static>(&self, event: &'static str, payload: Option<D>) {
+		// There's no window to wait for in the CLI
+		if *crate::cli::CLI_MODE {
+			return;
+		}
+
 		ignore! { self.window().emit(event, &payload) };
 	}
 